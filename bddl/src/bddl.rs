@@ -1,30 +1,46 @@
+use std::{fmt::{self, Display, Formatter}, str::FromStr};
+
+/// A boolean expression over sub conditions, `(a b)` as written is a conjunction. A sub
+/// condition on a cell off the board is false, also when it checks for a predicate not holding.
 #[derive(Debug, Clone)]
-pub struct Condition {
-    pub sub_cond: Vec<SubCondition>,
+pub enum Condition {
+    Atom(SubCondition),
+    /// The variable has the value.
+    Value { variable: String, value: i64 },
+    And(Vec<Condition>),
+    Or(Vec<Condition>),
+    Not(Box<Condition>),
 }
 
-#[derive(Debug, Clone, Copy)]
+/// A predicate on the cell `(x_e, y_e)` relative to the anchor, followed along the neighbour
+/// relations `via` in order.
+#[derive(Debug, Clone)]
 pub enum SubCondition {
     Id {
         pred: Pred,
         x_e: E,
         y_e: E,
+        via: Vec<String>,
     },
     Not {
         pred: Pred,
         x_e: E,
         y_e: E,
+        via: Vec<String>,
     },
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// What a cell holds. Cells start out `Open`.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub enum Pred {
     Open,
     White,
     Black,
+    /// A state the domain declares under `#predicates`.
+    Declared(String),
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum E {
     Add(i64),
     Sub(i64),
@@ -32,10 +48,18 @@ pub enum E {
     Identity,
     Min,
     Max,
+    /// An extra parameter of the action moved by `offset`. `index` is its position in
+    /// `Action::parameters`, set once the action is parsed.
+    Param { name: String, index: usize, offset: i64 },
 }
 
 impl E {
-    pub fn noramlize(&self, v: i64, max: i64) -> Option<i64> {
+    pub fn param(name: String, offset: i64) -> E {
+        E::Param { name, index: 0, offset }
+    }
+
+    /// The coordinate for the anchor coordinate `v` and the extra parameters `args`.
+    pub fn noramlize(&self, v: i64, max: i64, args: &[i64]) -> Option<i64> {
         match self {
             E::Add(u) => (v + u < max).then_some(v + u),
             E::Sub(u) => (v - u >= 0).then_some(v - u),
@@ -43,23 +67,41 @@ impl E {
             E::Identity => Some(v),
             E::Min => Some(0),
             E::Max => Some(max - 1),
+            E::Param { index, offset, .. } => Some(args[*index] + offset).filter(|u| (0..max).contains(u)),
         }
     }
-    
-    pub fn noramlize_t(&self, v: i64, max: i64) -> i64 {
+
+    /// The extra parameter referenced, if any.
+    pub fn parameter(&self) -> Option<usize> {
         match self {
-            E::Add(u) => v + u,
-            E::Sub(u) => v - u,
-            E::Int(u) => *u,
-            E::Identity => v,
-            E::Min => 0,
-            E::Max => max - 1,
+            E::Param { index, .. } => Some(*index),
+            _ => None,
         }
     }
+
+    /// Resolves a parameter reference: `anchor` stands for the anchor on a board given by
+    /// positions, anything else has to be among `parameters`. Fails with an unknown name.
+    fn bind(&mut self, anchor: Option<&str>, parameters: &[String]) -> Result<(), String> {
+        if let E::Param { name, index, offset } = self {
+            if Some(name.as_str()) == anchor {
+                *self = match *offset {
+                    0 => E::Identity,
+                    o if o > 0 => E::Add(o),
+                    o => E::Sub(-o),
+                };
+            } else {
+                *index = parameters.iter().position(|p| p == name).ok_or_else(|| name.clone())?;
+            }
+        }
+        Ok(())
+    }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Domain {
+    /// Cell states besides `open`, `white` and `black`.
+    pub predicates: Vec<String>,
+    pub variables: Vec<Variable>,
     pub black_actions: Vec<Action>,
     pub white_actions: Vec<Action>,
 }
@@ -67,17 +109,426 @@ pub struct Domain {
 #[derive(Debug, Clone)]
 pub struct Action {
     pub name: String,
+    /// Parameters after the anchor, each ranging over `Size::extent`.
+    pub parameters: Vec<String>,
     pub precondition: Condition,
-    pub effect: Condition,
+    /// Sets cells, or with `Not` opens a cell if it held the piece before the move. Of several
+    /// effects changing a cell the last one counts.
+    pub effect: Vec<SubCondition>,
+    /// Of several assignments to a variable the last one counts.
+    pub assignments: Vec<Assignment>,
+}
+
+/// A global state variable ranging over `0..=max`, not tied to a cell.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Variable {
+    pub name: String,
+    pub max: i64,
+}
+
+/// A change of a variable by a move, from its value before the move. A value out of the range
+/// of the variable leaves it unchanged, like an effect on a cell off the board.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Assignment {
+    Set { variable: String, value: i64 },
+    Add { variable: String, delta: i64 },
+}
+
+impl Assignment {
+    pub fn variable(&self) -> &str {
+        let (Assignment::Set { variable, .. } | Assignment::Add { variable, .. }) = self;
+        variable
+    }
+
+    /// The value after the move of a variable ranging over `0..=max`.
+    pub fn apply(&self, before: i64, max: i64) -> i64 {
+        let after = match self {
+            Assignment::Set { value, .. } => *value,
+            Assignment::Add { delta, .. } => before + delta,
+        };
+        if (0..=max).contains(&after) { after } else { before }
+    }
+}
+
+impl SubCondition {
+    /// Resolves the parameter references, see `E::bind`.
+    pub fn bind(&mut self, anchor: Option<&str>, parameters: &[String]) -> Result<(), String> {
+        let (SubCondition::Id { x_e, y_e, .. } | SubCondition::Not { x_e, y_e, .. }) = self;
+        x_e.bind(anchor, parameters)?;
+        y_e.bind(anchor, parameters)
+    }
+
+    pub fn pred(&self) -> &Pred {
+        let (SubCondition::Id { pred, .. } | SubCondition::Not { pred, .. }) = self;
+        pred
+    }
+
+    /// The neighbour relations followed from the cell, in order.
+    pub fn via(&self) -> &[String] {
+        let (SubCondition::Id { via, .. } | SubCondition::Not { via, .. }) = self;
+        via
+    }
+}
+
+impl Condition {
+    /// The sub conditions in the order they are written.
+    pub fn atoms(&self) -> Vec<&SubCondition> {
+        match self {
+            Condition::Atom(sub_condition) => vec![sub_condition],
+            Condition::Value { .. } => Vec::new(),
+            Condition::And(conditions) | Condition::Or(conditions) => conditions.iter().flat_map(Condition::atoms).collect(),
+            Condition::Not(condition) => condition.atoms(),
+        }
+    }
+
+    /// The variables the condition compares with values.
+    pub fn values(&self) -> Vec<(&str, i64)> {
+        match self {
+            Condition::Atom(_) => Vec::new(),
+            Condition::Value { variable, value } => vec![(variable, *value)],
+            Condition::And(conditions) | Condition::Or(conditions) => conditions.iter().flat_map(Condition::values).collect(),
+            Condition::Not(condition) => condition.values(),
+        }
+    }
+
+    fn atoms_mut(&mut self) -> Vec<&mut SubCondition> {
+        match self {
+            Condition::Atom(sub_condition) => vec![sub_condition],
+            Condition::Value { .. } => Vec::new(),
+            Condition::And(conditions) | Condition::Or(conditions) => conditions.iter_mut().flat_map(Condition::atoms_mut).collect(),
+            Condition::Not(condition) => condition.atoms_mut(),
+        }
+    }
+
+    /// The name a goal gives its anchor on a board given by positions.
+    pub fn anchor(&self) -> Option<String> {
+        self.atoms().into_iter().find_map(|sub_condition| {
+            let (SubCondition::Id { x_e, .. } | SubCondition::Not { x_e, .. }) = sub_condition;
+            match x_e {
+                E::Param { name, .. } => Some(name.clone()),
+                _ => None,
+            }
+        })
+    }
+
+    /// Resolves the parameter references, see `E::bind`.
+    pub fn bind(&mut self, anchor: Option<&str>, parameters: &[String]) -> Result<(), String> {
+        self.atoms_mut().into_iter().try_for_each(|sub_condition| sub_condition.bind(anchor, parameters))
+    }
+}
+
+impl Domain {
+    /// The domain with the players' actions exchanged. Black wins the swapped game, see
+    /// `Problem::swapped`, iff white wins this one.
+    pub fn swapped(&self) -> Domain {
+        Domain { black_actions: self.white_actions.clone(), white_actions: self.black_actions.clone(), ..self.clone() }
+    }
+
+    pub fn actions(&self, player: Player) -> &[Action] {
+        match player {
+            Player::Black => &self.black_actions,
+            Player::White => &self.white_actions,
+        }
+    }
+
+    /// Every state a cell can hold, in the order the backends number them.
+    pub fn states(&self) -> Vec<Pred> {
+        [Pred::Open, Pred::White, Pred::Black].into_iter()
+            .chain(self.predicates.iter().cloned().map(Pred::Declared))
+            .collect()
+    }
+
+    /// The number of `pred` in `states`.
+    pub fn state(&self, pred: &Pred) -> usize {
+        match pred {
+            Pred::Open => 0,
+            Pred::White => 1,
+            Pred::Black => 2,
+            Pred::Declared(name) => 3 + self.predicates.iter().position(|p| p == name).expect("predicate is declared"),
+        }
+    }
+
+    /// The number of the variable in `variables`.
+    pub fn variable(&self, name: &str) -> usize {
+        self.variables.iter().position(|v| v.name == name).expect("variable is declared")
+    }
+
+    /// The values of the variables before the first move.
+    pub fn initial_values(&self, problem: &Problem) -> Vec<i64> {
+        let mut values = vec![0; self.variables.len()];
+        for (name, value) in &problem.values {
+            values[self.variable(name)] = *value;
+        }
+        values
+    }
+
+    fn declares(&self, pred: &Pred) -> bool {
+        !matches!(pred, Pred::Declared(name) if !self.predicates.contains(name))
+    }
+
+    /// The variable is declared, and `value` is in its range.
+    fn in_range(&self, name: &str, value: i64) -> bool {
+        self.variables.iter().any(|v| v.name == name && (0..=v.max).contains(&value))
+    }
+
+    /// Checks that the action only uses predicates and variables the domain declares, and only
+    /// sets variables to values in their range.
+    pub fn check_action(&self, action: &Action) -> Result<(), String> {
+        if let Some(pred) = action.precondition.atoms().into_iter().chain(&action.effect).map(SubCondition::pred).find(|pred| !self.declares(pred)) {
+            return Err(format!("predicate {pred} is not declared"));
+        }
+        if let Some((name, _)) = action.precondition.values().into_iter().find(|(name, _)| !self.in_range(name, 0)) {
+            return Err(format!("variable {name} is not declared"));
+        }
+        let rejected = action.assignments.iter().find(|a| match a {
+            Assignment::Set { variable, value } => !self.in_range(variable, *value),
+            Assignment::Add { variable, .. } => !self.in_range(variable, 0),
+        });
+        match rejected {
+            Some(Assignment::Set { variable, value }) if self.in_range(variable, 0) => Err(format!("variable {variable} cannot be set to {value}")),
+            Some(assignment) => Err(format!("variable {} is not declared", assignment.variable())),
+            None => Ok(()),
+        }
+    }
+
+    /// Checks that the problem only uses predicates and variables the domain declares, and
+    /// starts the variables in their range, and that the problem declares the neighbour
+    /// relations both use.
+    pub fn check(&self, problem: &Problem) -> Result<(), String> {
+        let goals = problem.black_goals.iter().chain(&problem.white_goals);
+        let mut preds = problem.init.iter().map(|init| &init.pred).chain(goals.clone().flat_map(Condition::atoms).map(SubCondition::pred));
+        if let Some(pred) = preds.find(|pred| !self.declares(pred)) {
+            return Err(format!("predicate {pred} is not declared by the domain"));
+        }
+        let actions = self.black_actions.iter().chain(&self.white_actions);
+        let mut relations = goals.clone().flat_map(Condition::atoms)
+            .chain(actions.flat_map(|action| action.precondition.atoms().into_iter().chain(&action.effect)))
+            .flat_map(SubCondition::via);
+        if let Some(name) = relations.find(|name| problem.neighbours.iter().all(|relation| relation.name != **name)) {
+            return Err(format!("neighbour relation {name} is not declared by the problem"));
+        }
+        if let Some((name, _)) = goals.flat_map(Condition::values).find(|(name, _)| !self.in_range(name, 0)) {
+            return Err(format!("variable {name} is not declared by the domain"));
+        }
+        match problem.values.iter().find(|(name, value)| !self.in_range(name, *value)) {
+            Some((name, value)) => Err(format!("variable {name} cannot start at {value}")),
+            None => Ok(()),
+        }
+    }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Problem {
     pub size: Size,
+    /// Names of the cells `(i, 0)` of a board given by `#positions`, empty for a grid.
+    pub positions: Vec<String>,
+    pub neighbours: Vec<Relation>,
     pub init: Vec<InitPred>,
+    /// Initial values of variables, the others start at 0.
+    pub values: Vec<Value>,
     pub depth: u64,
     pub white_goals: Vec<Condition>,
     pub black_goals: Vec<Condition>,
+    pub stuck: Stuck,
+    pub turns: Turns,
+}
+
+/// What happens when the side to move has no applicable action.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Stuck {
+    /// The side to move loses.
+    #[default]
+    Loss,
+    /// The game ends without a winner.
+    Draw,
+    /// The side to move skips its turn.
+    Pass,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Player {
+    Black,
+    White,
+}
+
+impl Player {
+    pub fn opponent(self) -> Player {
+        match self {
+            Player::Black => Player::White,
+            Player::White => Player::Black,
+        }
+    }
+}
+
+/// The order of play. Turns alternate between the players, starting with `first`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Turns {
+    pub first: Player,
+    /// The number of moves in each turn, the last count repeating. Empty for one move per
+    /// turn, all counts are positive.
+    pub schedule: Vec<u64>,
+}
+
+impl Default for Turns {
+    fn default() -> Self {
+        Turns { first: Player::Black, schedule: Vec::new() }
+    }
+}
+
+impl Turns {
+    /// The player making the move `ply`, counted from 0.
+    pub fn mover(&self, ply: u64) -> Player {
+        self.locate(ply).1
+    }
+
+    /// The turn order from the move `ply` on.
+    pub fn after(&self, ply: u64) -> Turns {
+        let (turn, first, left) = self.locate(ply);
+        if self.schedule.is_empty() {
+            return Turns { first, schedule: Vec::new() };
+        }
+        let rest = &self.schedule[(turn + 1).min(self.schedule.len() - 1)..];
+        let mut schedule = [&[left], rest].concat();
+        while schedule.len() > 1 && schedule[schedule.len() - 2] == schedule[schedule.len() - 1] {
+            schedule.pop();
+        }
+        Turns { first, schedule }
+    }
+
+    /// The turn of the move `ply`, its player, and the moves left in it including this one.
+    fn locate(&self, mut ply: u64) -> (usize, Player, u64) {
+        let mut player = self.first;
+        for turn in 0.. {
+            let moves = self.schedule.get(turn).or(self.schedule.last()).copied().unwrap_or(1);
+            if ply < moves {
+                return (turn, player, moves - ply);
+            }
+            ply -= moves;
+            player = player.opponent();
+        }
+        unreachable!()
+    }
+}
+
+impl FromStr for Player {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "black" => Ok(Player::Black),
+            "white" => Ok(Player::White),
+            _ => Err(()),
+        }
+    }
+}
+
+/// A variable with its value.
+pub type Value = (String, i64);
+
+/// Coordinates `(x, y)` of a cell.
+pub type Cell = (i64, i64);
+
+/// A named partial function between cells, such as the neighbours in one direction on a hex
+/// board.
+#[derive(Debug, Clone)]
+pub struct Relation {
+    pub name: String,
+    pub pairs: Vec<(Cell, Cell)>,
+}
+
+impl Relation {
+    /// Fails on a position mapped twice, or an unknown name.
+    pub fn new(positions: &[String], relation: RelationRef) -> Result<Relation, Invalid> {
+        let RelationRef { name, pairs } = relation;
+        let mut relation = Relation { name, pairs: Vec::new() };
+        for (from, to) in pairs {
+            let location = from.location();
+            let from = resolve_cell(positions, from)?;
+            if relation.get(from).is_some() {
+                return Err(Invalid { location, reason: format!("a position is mapped twice by {}", relation.name) });
+            }
+            relation.pairs.push((from, resolve_cell(positions, to)?));
+        }
+        Ok(relation)
+    }
+
+    fn get(&self, cell: Cell) -> Option<Cell> {
+        self.pairs.iter().find(|(from, _)| *from == cell).map(|(_, to)| *to)
+    }
+}
+
+/// A `#neighbours` section as written, before position names are resolved.
+#[derive(Debug, Clone)]
+pub struct RelationRef {
+    pub name: String,
+    pub pairs: Vec<(CellRef, CellRef)>,
+}
+
+/// An entry of `#init` as written in a problem.
+#[derive(Debug, Clone)]
+pub enum InitRef {
+    Pred(Pred, CellRef),
+    Value(String, i64),
+}
+
+/// A cell as written in a problem, before position names are resolved.
+#[derive(Debug, Clone)]
+pub enum CellRef {
+    At(i64, i64),
+    /// A position name and where it occurs in the source.
+    Named(usize, String),
+}
+
+impl CellRef {
+    fn location(&self) -> usize {
+        match self {
+            CellRef::At(..) => 0,
+            CellRef::Named(location, _) => *location,
+        }
+    }
+}
+
+/// The names of `#positions`, failing on a repeated one.
+pub fn resolve_positions(positions: Vec<CellRef>) -> Result<Vec<String>, Invalid> {
+    let mut names: Vec<String> = Vec::new();
+    for position in positions {
+        match position {
+            CellRef::Named(location, name) if names.contains(&name) => return Err(Invalid { location, reason: format!("position {name} is declared twice") }),
+            CellRef::Named(_, name) => names.push(name),
+            CellRef::At(..) => unreachable!("positions are names"),
+        }
+    }
+    Ok(names)
+}
+
+/// The cells and the variables among initial entries, failing on an unknown name.
+pub fn resolve_init(positions: &[String], init: Vec<InitRef>) -> Result<(Vec<InitPred>, Vec<Value>), Invalid> {
+    let (mut cells, mut values) = (Vec::new(), Vec::new());
+    for entry in init {
+        match entry {
+            InitRef::Pred(pred, cell) => cells.push(resolve_cell(positions, cell).map(|(x, y)| InitPred { pred, x, y })?),
+            InitRef::Value(variable, value) => values.push((variable, value)),
+        }
+    }
+    Ok((cells, values))
+}
+
+/// The cell a reference denotes, failing on an unknown name.
+pub fn resolve_cell(positions: &[String], cell: CellRef) -> Result<Cell, Invalid> {
+    match cell {
+        CellRef::At(x, y) => Ok((x, y)),
+        CellRef::Named(location, name) => match positions.iter().position(|p| *p == name) {
+            Some(i) => Ok((i as i64, 0)),
+            None => Err(Invalid { location, reason: format!("unknown position {name}") }),
+        },
+    }
+}
+
+/// What a grammar action rejects, and where.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Invalid {
+    pub location: usize,
+    pub reason: String,
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -86,9 +537,264 @@ pub struct Size {
     pub y: i64,
 }
 
-#[derive(Debug)]
+impl Size {
+    /// The values an extra action parameter ranges over, enough for a coordinate on either axis.
+    pub fn extent(&self) -> i64 {
+        self.x.max(self.y)
+    }
+
+    /// Every assignment of values to `arity` extra parameters.
+    pub fn assignments(&self, arity: usize) -> Vec<Vec<i64>> {
+        (0..arity).fold(vec![Vec::new()], |assignments, _| assignments.into_iter()
+            .flat_map(|args| (0..self.extent()).map(move |v| [args.as_slice(), &[v]].concat()))
+            .collect())
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct InitPred {
     pub pred: Pred,
     pub x: i64,
     pub y: i64,
 }
+
+impl Problem {
+    /// The problem with the players' goals and turns exchanged.
+    pub fn swapped(&self) -> Problem {
+        Problem {
+            black_goals: self.white_goals.clone(),
+            white_goals: self.black_goals.clone(),
+            turns: Turns { first: self.turns.first.opponent(), schedule: self.turns.schedule.clone() },
+            ..self.clone()
+        }
+    }
+
+    /// The problem starting from the position given by `init` and `values` as the move `ply`.
+    /// Fails if `ply` exceeds the depth.
+    pub fn with_board(&self, init: Vec<InitPred>, values: Vec<Value>, ply: u64) -> Result<Problem, String> {
+        let depth = self.depth.checked_sub(ply).ok_or_else(|| format!("move {} exceeds the depth {}", ply, self.depth))?;
+        Ok(Problem { init, values, depth, turns: self.turns.after(ply), ..self.clone() })
+    }
+
+    pub fn goals(&self, player: Player) -> &[Condition] {
+        match player {
+            Player::Black => &self.black_goals,
+            Player::White => &self.white_goals,
+        }
+    }
+
+    pub fn cells(&self) -> impl Iterator<Item = Cell> {
+        let size = self.size;
+        (0..size.x).flat_map(move |x| (0..size.y).map(move |y| (x, y)))
+    }
+
+    /// The cell `(x_e, y_e)` from the anchor `(x, y)` and the extra parameters `args`, followed
+    /// along `via`, if it is on the board.
+    pub fn target(&self, x_e: &E, y_e: &E, via: &[String], x: i64, y: i64, args: &[i64]) -> Option<Cell> {
+        let cell = (x_e.noramlize(x, self.size.x, args)?, y_e.noramlize(y, self.size.y, args)?);
+        if !(0..self.size.x).contains(&cell.0) || !(0..self.size.y).contains(&cell.1) {
+            return None;
+        }
+        via.iter().try_fold(cell, |cell, name| {
+            let relation = self.neighbours.iter().find(|r| r.name == *name)
+                .unwrap_or_else(|| panic!("unknown neighbour relation {}", name));
+            relation.get(cell)
+        })
+    }
+
+    /// Every anchor with the cell it reaches through `(x_e, y_e)` and `via`. The anchor comes
+    /// with the values of the extra parameters up to the last one the cell mentions. Neighbour
+    /// relations have no arithmetic, so the symbolic backends match anchors against these.
+    pub fn targets(&self, x_e: &E, y_e: &E, via: &[String]) -> Vec<(Cell, Vec<i64>, Cell)> {
+        let arity = x_e.parameter().max(y_e.parameter()).map_or(0, |i| i + 1);
+        let assignments = self.size.assignments(arity);
+        self.cells()
+            .flat_map(|(x, y)| assignments.iter().map(move |args| ((x, y), args)))
+            .filter_map(|(anchor, args)| Some((anchor, args.clone(), self.target(x_e, y_e, via, anchor.0, anchor.1, args)?)))
+            .collect()
+    }
+
+    fn cell_name(&self, (x, y): Cell) -> String {
+        match self.positions.get(x as usize) {
+            Some(name) => name.clone(),
+            None => format!("{},{}", x, y),
+        }
+    }
+}
+
+impl E {
+    fn fmt_axis(&self, f: &mut Formatter<'_>, axis: &str) -> fmt::Result {
+        match self {
+            E::Add(i) => write!(f, "?{axis}+{i}"),
+            E::Sub(i) => write!(f, "?{axis}-{i}"),
+            E::Int(i) => write!(f, "{i}"),
+            E::Identity => write!(f, "?{axis}"),
+            E::Min => write!(f, "{axis}min"),
+            E::Max => write!(f, "{axis}max"),
+            E::Param { name, offset, .. } => match offset {
+                0 => write!(f, "?{name}"),
+                o if *o > 0 => write!(f, "?{name}+{o}"),
+                o => write!(f, "?{name}-{}", -o),
+            },
+        }
+    }
+}
+
+impl Display for Pred {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Pred::Open => write!(f, "open"),
+            Pred::White => write!(f, "white"),
+            Pred::Black => write!(f, "black"),
+            Pred::Declared(name) => write!(f, "{name}"),
+        }
+    }
+}
+
+impl Display for Player {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Player::Black => write!(f, "black"),
+            Player::White => write!(f, "white"),
+        }
+    }
+}
+
+impl Display for SubCondition {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let (SubCondition::Id { pred, x_e, y_e, via } | SubCondition::Not { pred, x_e, y_e, via }) = self;
+        if let SubCondition::Not { .. } = self {
+            write!(f, "NOT(")?;
+        }
+        write!(f, "{pred}(")?;
+        via.iter().rev().try_for_each(|name| write!(f, "{name}("))?;
+        x_e.fmt_axis(f, "x")?;
+        write!(f, ",")?;
+        y_e.fmt_axis(f, "y")?;
+        via.iter().try_for_each(|_| write!(f, ")"))?;
+        write!(f, ")")?;
+        if let SubCondition::Not { .. } = self {
+            write!(f, ")")?;
+        }
+        Ok(())
+    }
+}
+
+impl Condition {
+    /// The condition nested in another one.
+    fn fmt_nested(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let (keyword, conditions) = match self {
+            Condition::Atom(sub_condition) => return write!(f, "{sub_condition}"),
+            Condition::Value { variable, value } => return write!(f, "{variable} = {value}"),
+            Condition::And(conditions) => ("AND", conditions),
+            Condition::Or(conditions) => ("OR", conditions),
+            // A negated atom would read back as a sub condition, which is false off the board.
+            Condition::Not(condition) if matches!(**condition, Condition::Atom(_)) => {
+                write!(f, "NOT(AND(")?;
+                condition.fmt_nested(f)?;
+                return write!(f, "))");
+            },
+            Condition::Not(condition) => {
+                write!(f, "NOT(")?;
+                condition.fmt_nested(f)?;
+                return write!(f, ")");
+            },
+        };
+        write!(f, "{keyword}(")?;
+        fmt_list(f, conditions)?;
+        write!(f, ")")
+    }
+}
+
+fn fmt_list(f: &mut Formatter<'_>, conditions: &[Condition]) -> fmt::Result {
+    conditions.iter().enumerate().try_for_each(|(i, condition)| {
+        if i > 0 {
+            write!(f, " ")?;
+        }
+        condition.fmt_nested(f)
+    })
+}
+
+impl Display for Condition {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "(")?;
+        match self {
+            Condition::And(conditions) => fmt_list(f, conditions)?,
+            condition => condition.fmt_nested(f)?,
+        }
+        write!(f, ")")
+    }
+}
+
+impl Display for Assignment {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Assignment::Set { variable, value } => write!(f, "{variable} = {value}"),
+            Assignment::Add { variable, delta } if *delta < 0 => write!(f, "{variable} - {}", -delta),
+            Assignment::Add { variable, delta } => write!(f, "{variable} + {delta}"),
+        }
+    }
+}
+
+impl Display for Action {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        writeln!(f, ":action {}", self.name)?;
+        let parameters = self.parameters.iter().map(|p| format!(", ?{p}")).collect::<String>();
+        writeln!(f, ":parameters (?x, ?y{parameters})")?;
+        writeln!(f, ":precondition {}", self.precondition)?;
+        let effect = self.effect.iter().map(|e| e.to_string())
+            .chain(self.assignments.iter().map(|a| a.to_string()))
+            .collect::<Vec<_>>();
+        writeln!(f, ":effect ({})", effect.join(" "))
+    }
+}
+
+impl Display for Domain {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        if !self.predicates.is_empty() {
+            writeln!(f, "#predicates\n({})", self.predicates.join(" "))?;
+        }
+        if !self.variables.is_empty() {
+            let variables = self.variables.iter().map(|v| format!("({} {})", v.name, v.max)).collect::<Vec<_>>();
+            writeln!(f, "#variables\n{}", variables.join(" "))?;
+        }
+        writeln!(f, "#blackactions")?;
+        self.black_actions.iter().try_for_each(|action| write!(f, "{action}"))?;
+        writeln!(f, "#whiteactions")?;
+        self.white_actions.iter().try_for_each(|action| write!(f, "{action}"))
+    }
+}
+
+impl Display for Problem {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        if self.positions.is_empty() {
+            writeln!(f, "#boardsize\n{} {}", self.size.x, self.size.y)?;
+        } else {
+            writeln!(f, "#positions\n({})", self.positions.join(" "))?;
+        }
+        for relation in &self.neighbours {
+            let pairs = relation.pairs.iter()
+                .map(|(from, to)| format!("({} {})", self.cell_name(*from), self.cell_name(*to)))
+                .collect::<Vec<_>>();
+            writeln!(f, "#neighbours {}\n{}", relation.name, pairs.join(" "))?;
+        }
+        let init = self.init.iter().map(|p| format!("{}({})", p.pred, self.cell_name((p.x, p.y))))
+            .chain(self.values.iter().map(|(name, value)| format!("{name} = {value}")))
+            .collect::<Vec<_>>();
+        writeln!(f, "#init\n({})", init.join(" "))?;
+        writeln!(f, "#depth\n{}", self.depth)?;
+        if self.turns != Turns::default() {
+            let counts = self.turns.schedule.iter().map(|moves| format!(" {moves}")).collect::<String>();
+            writeln!(f, "#turnorder {}{}", self.turns.first, counts)?;
+        }
+        writeln!(f, "#blackgoal")?;
+        self.black_goals.iter().try_for_each(|goal| writeln!(f, "{goal}"))?;
+        writeln!(f, "#whitegoal")?;
+        self.white_goals.iter().try_for_each(|goal| writeln!(f, "{goal}"))?;
+        match self.stuck {
+            Stuck::Loss => Ok(()),
+            Stuck::Draw => writeln!(f, "#nomove draw"),
+            Stuck::Pass => writeln!(f, "#nomove pass"),
+        }
+    }
+}
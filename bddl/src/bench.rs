@@ -0,0 +1,324 @@
+//! Regression benchmarks, see `bddl --help`.
+
+use std::{collections::BTreeMap, path::{Path, PathBuf}, process::{Child, Command, Stdio}, time::{Duration, Instant}, io::Read};
+
+use crate::{flag, lexer::ParseError, parse_domain, parse_problem, position::PositionEncoding, solver_qbf::Transition};
+
+const DEFAULT_BACKENDS: &str = "explicit,z3,qbf,qbf/flatten";
+
+pub struct Entry {
+    pub name: String,
+    pub domain: PathBuf,
+    pub problem: PathBuf,
+    /// Whether black wins, if known.
+    pub expected: Option<bool>,
+}
+
+impl Entry {
+    fn expected(&self) -> &'static str {
+        match self.expected {
+            Some(true) => "win",
+            Some(false) => "loss",
+            None => "unknown",
+        }
+    }
+
+    /// The first syntax error in the domain or problem, with the file it is in.
+    fn parse_error(&self) -> Option<(&Path, ParseError)> {
+        let check = |path: &Path, parse: fn(&str) -> Result<(), ParseError>| {
+            let src = std::fs::read_to_string(path).unwrap_or_else(|e| panic!("cannot read {}: {}", path.display(), e));
+            parse(&src).err()
+        };
+        check(&self.domain, |src| parse_domain(src).map(drop)).map(|e| (self.domain.as_path(), e))
+            .or_else(|| check(&self.problem, |src| parse_problem(src).map(drop)).map(|e| (self.problem.as_path(), e)))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Outcome {
+    Win,
+    Loss,
+    Unknown,
+    Timeout,
+    Error,
+    Unsupported,
+}
+
+impl Outcome {
+    fn name(self) -> &'static str {
+        match self {
+            Outcome::Win => "win",
+            Outcome::Loss => "loss",
+            Outcome::Unknown => "unknown",
+            Outcome::Timeout => "timeout",
+            Outcome::Error => "error",
+            Outcome::Unsupported => "unsupported",
+        }
+    }
+}
+
+struct Run<'a> {
+    entry: &'a Entry,
+    backend: &'a str,
+    outcome: Outcome,
+    seconds: Option<f64>,
+    size: Option<usize>,
+}
+
+impl Run<'_> {
+    /// A definite result that contradicts the manifest.
+    fn wrong(&self) -> bool {
+        matches!((self.outcome, self.entry.expected), (Outcome::Win, Some(false)) | (Outcome::Loss, Some(true)))
+    }
+}
+
+/// Number of clauses announced in the header of QDIMACS text.
+pub fn qdimacs_clauses(cnf: &str) -> usize {
+    cnf.lines().next()
+        .and_then(|header| header.split(' ').nth(3))
+        .and_then(|clauses| clauses.parse().ok())
+        .expect("qdimacs header")
+}
+
+/// The line a single run prints with `--report`, read back by `parse_report`.
+pub fn report_line(won: Option<bool>, size: Option<usize>, elapsed: Duration) -> String {
+    let outcome = match won {
+        Some(true) => Outcome::Win,
+        Some(false) => Outcome::Loss,
+        None => Outcome::Unknown,
+    };
+    let size = size.map_or("-".to_owned(), |size| size.to_string());
+    format!("report {} {} {}", outcome.name(), size, elapsed.as_secs_f64())
+}
+
+fn parse_report(stdout: &str) -> Option<(Outcome, Option<usize>, f64)> {
+    let line = stdout.lines().find(|line| line.starts_with("report "))?;
+    let mut fields = line.split(' ').skip(1);
+    let outcome = match fields.next()? {
+        "win" => Outcome::Win,
+        "loss" => Outcome::Loss,
+        _ => Outcome::Unknown,
+    };
+    let size = fields.next()?.parse().ok();
+    let seconds = fields.next()?.parse().ok()?;
+    Some((outcome, size, seconds))
+}
+
+/// The entries of `<dir>/manifest`, one `<name> <domain> <problem> <win|loss>` per line with
+/// paths relative to `dir`, `#` starting a comment.
+pub fn read_manifest(dir: &Path) -> Vec<Entry> {
+    let path = dir.join("manifest");
+    let manifest = std::fs::read_to_string(&path).unwrap_or_else(|e| panic!("cannot read {}: {}", path.display(), e));
+    manifest.lines()
+        .map(|line| line.split('#').next().unwrap().trim())
+        .filter(|line| !line.is_empty())
+        .map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let [name, domain, problem, expected] = fields[..] else {
+                panic!("manifest line needs name, domain, problem and expected result: {}", line);
+            };
+            let expected = match expected {
+                "win" => Some(true),
+                "loss" => Some(false),
+                other => panic!("expected result must be win or loss, not {}", other),
+            };
+            Entry { name: name.to_owned(), domain: dir.join(domain), problem: dir.join(problem), expected }
+        })
+        .collect()
+}
+
+/// The part of a file name before its extension, and before `domain` for domains.
+fn stem(path: &Path) -> String {
+    path.file_stem().unwrap().to_string_lossy().replacen("domain", "", 1)
+}
+
+fn is_domain(path: &Path) -> bool {
+    path.file_name().unwrap().to_string_lossy().contains("domain")
+}
+
+/// Problems of the published benchmark layout, paired with their domains: every `.ig` file
+/// with `domain` in its name is a domain, every other one a problem. A problem uses the domain
+/// of the nearest directory whose name without `domain` is the longest prefix of its name.
+pub fn discover(root: &Path) -> Vec<Entry> {
+    fn walk(root: &Path, dir: &Path, domains: &[PathBuf], entries: &mut Vec<Entry>) {
+        let mut paths: Vec<PathBuf> = std::fs::read_dir(dir)
+            .unwrap_or_else(|e| panic!("cannot read {}: {}", dir.display(), e))
+            .map(|entry| entry.unwrap().path())
+            .collect();
+        paths.sort();
+        let ig = |path: &&PathBuf| path.is_file() && path.extension().is_some_and(|e| e == "ig");
+        // Nearest directory first, so its domains win ties with those of parents.
+        let mut visible: Vec<PathBuf> = paths.iter().filter(ig).filter(|p| is_domain(p)).cloned().collect();
+        visible.extend_from_slice(domains);
+        for problem in paths.iter().filter(ig).filter(|p| !is_domain(p)) {
+            let problem_stem = stem(problem);
+            let domain = visible.iter()
+                .filter(|domain| problem_stem.starts_with(&stem(domain)))
+                .reduce(|best, domain| if stem(domain).len() > stem(best).len() { domain } else { best });
+            match domain {
+                Some(domain) => {
+                    let name = problem.strip_prefix(root).unwrap().with_extension("").to_string_lossy().into_owned();
+                    entries.push(Entry { name, domain: domain.clone(), problem: problem.clone(), expected: None });
+                },
+                None => eprintln!("{}: no domain", problem.display()),
+            }
+        }
+        for sub in paths.iter().filter(|path| path.is_dir()) {
+            walk(root, sub, &visible, entries);
+        }
+    }
+    let mut entries = Vec::new();
+    walk(root, root, &[], &mut entries);
+    entries
+}
+
+/// Command line arguments selecting a backend spec such as `qbf/onehot/flatten`.
+fn backend_args(spec: &str) -> Vec<String> {
+    let mut parts = spec.split('/');
+    let backend = parts.next().unwrap();
+    let mut args = vec!["--backend".to_owned(), backend.to_owned()];
+    for part in parts {
+        if part == "flatten" {
+            args.push("--flatten".to_owned());
+        } else if part.parse::<PositionEncoding>().is_ok() {
+            args.extend(["--position".to_owned(), part.to_owned()]);
+        } else if part.parse::<Transition>().is_ok() {
+            args.extend(["--transition".to_owned(), part.to_owned()]);
+        } else {
+            panic!("unknown option {} in backend {}", part, spec);
+        }
+    }
+    args
+}
+
+/// Runs the entry as a child process of this binary, killed once it exceeds `timeout`.
+fn run_one<'a>(entry: &'a Entry, backend: &'a str, timeout: Duration) -> Run<'a> {
+    let mut command = Command::new(std::env::current_exe().unwrap());
+    command.arg(&entry.problem)
+        .arg(&entry.domain)
+        .args(backend_args(backend))
+        .arg("--report")
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null());
+    // Own process group, so bloqqer and depqbf die with the run.
+    #[cfg(unix)]
+    std::os::unix::process::CommandExt::process_group(&mut command, 0);
+    let mut child = command.spawn().unwrap();
+    let start = Instant::now();
+    let status = loop {
+        if let Some(status) = child.try_wait().unwrap() {
+            break Some(status);
+        }
+        if start.elapsed() > timeout {
+            kill(&mut child);
+            break None;
+        }
+        std::thread::sleep(Duration::from_millis(10));
+    };
+    let mut stdout = String::new();
+    child.stdout.take().unwrap().read_to_string(&mut stdout).unwrap();
+    let (outcome, size, seconds) = match (status, parse_report(&stdout)) {
+        (None, _) => (Outcome::Timeout, None, None),
+        (Some(status), Some((outcome, size, seconds))) if status.success() => (outcome, size, Some(seconds)),
+        _ => (Outcome::Error, None, None),
+    };
+    Run { entry, backend, outcome, seconds, size }
+}
+
+/// Kills the run with its process group.
+#[cfg(unix)]
+fn kill(child: &mut Child) {
+    let _ = Command::new("kill").args(["-s", "KILL", "--"]).arg(format!("-{}", child.id())).status();
+    let _ = child.wait();
+}
+
+/// Kills the run, elsewhere without the solver processes it started.
+#[cfg(not(unix))]
+fn kill(child: &mut Child) {
+    let _ = child.kill();
+    let _ = child.wait();
+}
+
+fn csv(runs: &[Run]) -> String {
+    let mut out = String::from("name,backend,expected,result,correct,seconds,size\n");
+    for run in runs {
+        out.push_str(&format!(
+            "{},{},{},{},{},{},{}\n",
+            run.entry.name,
+            run.backend,
+            run.entry.expected(),
+            run.outcome.name(),
+            run.entry.expected.map_or(String::new(), |_| (!run.wrong()).to_string()),
+            run.seconds.map_or(String::new(), |s| format!("{:.3}", s)),
+            run.size.map_or(String::new(), |s| s.to_string()),
+        ));
+    }
+    out
+}
+
+fn json_string(s: &str) -> String {
+    format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+fn json(runs: &[Run]) -> String {
+    let rows: Vec<String> = runs.iter()
+        .map(|run| format!(
+            "  {{\"name\": {}, \"backend\": {}, \"expected\": \"{}\", \"result\": \"{}\", \"correct\": {}, \"seconds\": {}, \"size\": {}}}",
+            json_string(&run.entry.name),
+            json_string(run.backend),
+            run.entry.expected(),
+            run.outcome.name(),
+            run.entry.expected.map_or("null".to_owned(), |_| (!run.wrong()).to_string()),
+            run.seconds.map_or("null".to_owned(), |s| format!("{:.3}", s)),
+            run.size.map_or("null".to_owned(), |s| s.to_string()),
+        ))
+        .collect();
+    format!("[\n{}\n]\n", rows.join(",\n"))
+}
+
+pub fn run() {
+    let dir = PathBuf::from(std::env::args().nth(2).expect("usage: bddl bench <dir>"));
+    let timeout = Duration::from_secs_f64(flag("--timeout").map_or(60.0, |t| t.parse().expect("timeout in seconds")));
+    let backends = flag("--backends").unwrap_or(DEFAULT_BACKENDS.to_owned());
+    let backends: Vec<&str> = backends.split(',').collect();
+    let entries = if dir.join("manifest").exists() { read_manifest(&dir) } else { discover(&dir) };
+
+    let mut unsupported = BTreeMap::<String, Vec<&str>>::new();
+    let parses: Vec<bool> = entries.iter()
+        .map(|entry| match entry.parse_error() {
+            Some((path, error)) => {
+                eprintln!("{}:{}", path.display(), error);
+                unsupported.entry(error.construct()).or_default().push(&entry.name);
+                false
+            },
+            None => true,
+        })
+        .collect();
+    for (construct, names) in &unsupported {
+        eprintln!("unsupported {} in {} instances: {}", construct, names.len(), names.join(" "));
+    }
+    if std::env::args().any(|arg| arg == "--parse-only") {
+        eprintln!("{} of {} instances parse", parses.iter().filter(|p| **p).count(), entries.len());
+        std::process::exit(if unsupported.is_empty() { 0 } else { 1 });
+    }
+
+    let runs: Vec<Run> = entries.iter().zip(parses)
+        .flat_map(|(entry, parses)| backends.iter().map(move |backend| (entry, parses, *backend)))
+        .map(|(entry, parses, backend)| {
+            if !parses {
+                return Run { entry, backend, outcome: Outcome::Unsupported, seconds: None, size: None };
+            }
+            let run = run_one(entry, backend, timeout);
+            eprintln!("{} {}: {}", entry.name, backend, run.outcome.name());
+            run
+        })
+        .collect();
+    match flag("--format").as_deref().unwrap_or("csv") {
+        "csv" => print!("{}", csv(&runs)),
+        "json" => print!("{}", json(&runs)),
+        format => panic!("unknown format {}", format),
+    }
+    if runs.iter().any(Run::wrong) {
+        std::process::exit(1);
+    }
+}
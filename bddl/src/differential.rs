@@ -0,0 +1,384 @@
+//! Random small instances on which every locally available backend has to agree.
+
+use std::{io::Write, path::PathBuf, process::{Command, Stdio}, time::{Duration, Instant}};
+
+use crate::{
+    bddl::{Action, Assignment, Condition, Domain, InitPred, Player, Pred, Problem, Size, Stuck, SubCondition, Turns, Variable, E},
+    parse_domain, parse_problem,
+    position::PositionEncoding,
+    qbf::Formula,
+    solver::{self, Board, FirstMove}, solver_qbf::{self, Transition}, solver_z3,
+    tests::{depqbf_available, Rng},
+};
+
+/// An offset, referring to one of the first `parameters` extra parameters now and then.
+fn random_e(rng: &mut Rng, max: i64, parameters: usize) -> E {
+    if parameters > 0 && rng.percent(20) {
+        let index = rng.range(0, parameters as i64 - 1) as usize;
+        return E::Param { name: format!("p{index}"), index, offset: rng.range(-1, 1) };
+    }
+    match rng.range(0, 9) {
+        0..=3 => E::Identity,
+        4 | 5 => E::Add(rng.range(1, 2)),
+        6 | 7 => E::Sub(rng.range(1, 2)),
+        8 => if rng.percent(50) { E::Min } else { E::Max },
+        _ => E::Int(rng.range(0, max - 1)),
+    }
+}
+
+fn random_sub_condition(rng: &mut Rng, size: Size, pred: Pred, parameters: usize) -> SubCondition {
+    let (x_e, y_e) = (random_e(rng, size.x, parameters), random_e(rng, size.y, parameters));
+    if rng.percent(20) {
+        SubCondition::Not { pred, x_e, y_e, via: Vec::new() }
+    } else {
+        SubCondition::Id { pred, x_e, y_e, via: Vec::new() }
+    }
+}
+
+fn relative(e: &E) -> bool {
+    matches!(e, E::Identity | E::Add(_) | E::Sub(_) | E::Param { .. })
+}
+
+/// An action placing `pred` at the anchor, now and then with extra parameters. Other cells are
+/// checked and set to any of the `states`. Further effects, deletions among them, only target
+/// relative offsets the precondition already checks, so they stay on the board. They may
+/// change a cell more than once.
+fn random_action(rng: &mut Rng, size: Size, pred: Pred, states: &[Pred], variables: &[Variable], name: String) -> Action {
+    let arity = if rng.percent(30) { rng.range(1, 2) } else { 0 };
+    let parameters: Vec<String> = (0..arity).map(|i| format!("p{i}")).collect();
+    let mut precondition = vec![SubCondition::Id { pred: Pred::Open, x_e: E::Identity, y_e: E::Identity, via: Vec::new() }];
+    for _ in 0..rng.range(0, 2) {
+        let pred = rng.pick(states);
+        precondition.push(random_sub_condition(rng, size, pred, parameters.len()));
+    }
+    let mut effect = vec![SubCondition::Id { pred, x_e: E::Identity, y_e: E::Identity, via: Vec::new() }];
+    for sub_condition in &precondition {
+        let (SubCondition::Id { x_e, y_e, .. } | SubCondition::Not { x_e, y_e, .. }) = sub_condition.clone();
+        if relative(&x_e) && relative(&y_e) && rng.percent(30) {
+            let pred = rng.pick(states);
+            effect.push(if pred != Pred::Open && rng.percent(40) {
+                SubCondition::Not { pred, x_e, y_e, via: Vec::new() }
+            } else {
+                SubCondition::Id { pred, x_e, y_e, via: Vec::new() }
+            });
+        }
+    }
+    let mut conditions: Vec<Condition> = precondition.into_iter().map(Condition::Atom).collect();
+    if rng.percent(30) {
+        let pred = rng.pick(states);
+        conditions.push(random_compound(rng, size, pred, parameters.len()));
+    }
+    let mut assignments = Vec::new();
+    if !variables.is_empty() && rng.percent(40) {
+        conditions.push(random_value(rng, variables));
+    }
+    if !variables.is_empty() && rng.percent(50) {
+        let variable = rng.pick(variables);
+        assignments.push(if rng.percent(50) {
+            Assignment::Set { value: rng.range(0, variable.max), variable: variable.name }
+        } else {
+            Assignment::Add { delta: rng.pick(&[-2, -1, 1, 2]), variable: variable.name }
+        });
+    }
+    Action { name, parameters, precondition: Condition::And(conditions), effect, assignments }
+}
+
+/// A variable compared with a value in its range, now and then negated.
+fn random_value(rng: &mut Rng, variables: &[Variable]) -> Condition {
+    let variable = rng.pick(variables);
+    let value = Condition::Value { value: rng.range(0, variable.max), variable: variable.name };
+    if rng.percent(30) { Condition::Not(Box::new(value)) } else { value }
+}
+
+/// A disjunction or negation of sub conditions.
+fn random_compound(rng: &mut Rng, size: Size, pred: Pred, parameters: usize) -> Condition {
+    let atom = |rng: &mut Rng| Condition::Atom(random_sub_condition(rng, size, pred.clone(), parameters));
+    if rng.percent(50) {
+        Condition::Or((0..rng.range(1, 2)).map(|_| atom(rng)).collect())
+    } else {
+        Condition::Not(Box::new(Condition::And((0..rng.range(1, 2)).map(|_| atom(rng)).collect())))
+    }
+}
+
+fn random_goals(rng: &mut Rng, size: Size, pred: Pred, states: &[Pred], variables: &[Variable], min: i64) -> Vec<Condition> {
+    (0..rng.range(min, 2))
+        .map(|_| {
+            let conditions = (0..rng.range(1, 3))
+                .map(|_| {
+                    let pred = if rng.percent(80) { pred.clone() } else { rng.pick(states) };
+                    if !variables.is_empty() && rng.percent(15) {
+                        random_value(rng, variables)
+                    } else if rng.percent(20) {
+                        random_compound(rng, size, pred, 0)
+                    } else {
+                        Condition::Atom(random_sub_condition(rng, size, pred, 0))
+                    }
+                })
+                .collect();
+            Condition::And(conditions)
+        })
+        .collect()
+}
+
+/// Now and then white moving first, or turns of more than one move.
+fn random_turns(rng: &mut Rng) -> Turns {
+    let first = if rng.percent(30) { Player::White } else { Player::Black };
+    let schedule = if rng.percent(30) { (0..rng.range(1, 2)).map(|_| rng.range(1, 2) as u64).collect() } else { Vec::new() };
+    Turns { first, schedule }
+}
+
+fn random_instance(seed: u64) -> (Problem, Domain, solver_qbf::Options) {
+    let mut rng = Rng(seed);
+    let size = Size { x: rng.range(2, 4), y: rng.range(2, 4) };
+    let predicates: Vec<String> = (0..if rng.percent(30) { rng.range(1, 2) } else { 0 }).map(|i| format!("s{i}")).collect();
+    let pieces: Vec<Pred> = [Pred::Black, Pred::White].into_iter().chain(predicates.iter().cloned().map(Pred::Declared)).collect();
+    let states: Vec<Pred> = [Pred::Open].into_iter().chain(pieces.iter().cloned()).collect();
+    let variables: Vec<Variable> = (0..if rng.percent(30) { rng.range(1, 2) } else { 0 })
+        .map(|i| Variable { name: format!("v{i}"), max: rng.range(1, 3) })
+        .collect();
+    let black_actions = (0..rng.range(1, 2)).map(|i| random_action(&mut rng, size, Pred::Black, &states, &variables, format!("move{i}"))).collect();
+    let white_actions = (0..rng.range(1, 2)).map(|i| random_action(&mut rng, size, Pred::White, &states, &variables, format!("move{i}"))).collect();
+    let mut init = Vec::new();
+    for x in 0..size.x {
+        for y in 0..size.y {
+            if rng.percent(20) {
+                init.push(InitPred { pred: rng.pick(&pieces), x, y });
+            }
+        }
+    }
+    let mut values = Vec::new();
+    for variable in &variables {
+        if rng.percent(50) {
+            values.push((variable.name.clone(), rng.range(0, variable.max)));
+        }
+    }
+    let problem = Problem {
+        size,
+        positions: Vec::new(),
+        neighbours: Vec::new(),
+        init,
+        values,
+        depth: rng.range(1, 3) as u64,
+        black_goals: random_goals(&mut rng, size, Pred::Black, &states, &variables, 1),
+        white_goals: random_goals(&mut rng, size, Pred::White, &states, &variables, 0),
+        stuck: rng.pick(&[Stuck::Loss, Stuck::Draw, Stuck::Pass]),
+        turns: random_turns(&mut rng),
+    };
+    let options = solver_qbf::Options {
+        flatten: rng.percent(50),
+        position: rng.pick(&[PositionEncoding::Log, PositionEncoding::OneHot, PositionEncoding::Order, PositionEncoding::CellIndex]),
+        transition: rng.pick(&[Transition::Chained, Transition::Frame]),
+    };
+    (problem, Domain { predicates, variables, black_actions, white_actions }, options)
+}
+
+/// Whether black wins according to each backend that reaches a verdict. Instances go through
+/// their textual form first, so a reproducer behaves exactly like the instance it was written
+/// from.
+fn verdicts(problem: &Problem, domain: &Domain, options: solver_qbf::Options) -> Vec<(String, bool)> {
+    let problem = parse_problem(&problem.to_string()).unwrap();
+    let domain = parse_domain(&domain.to_string()).unwrap();
+    let mut verdicts = vec![("explicit".to_owned(), solver::solve(&problem, &domain).is_some())];
+
+    // A win only counts with a first move that wins. When z3 gives up it has no verdict, like
+    // depqbf running out of time.
+    if let Ok(won) = solver_z3::solve_move(&problem, &domain, None) {
+        let z3 = match won {
+            Some(Some(first)) => wins_with(&problem, &domain, &first),
+            won => won.is_some(),
+        };
+        verdicts.push(("z3".to_owned(), z3));
+    }
+
+    if depqbf_available() {
+        if let Some(verdict) = check_qbf(solver_qbf::solve(problem, domain, options)) {
+            verdicts.push((format!("qbf {options:?}"), verdict));
+        }
+    }
+    verdicts
+}
+
+/// Whether black, moving first, wins with the move.
+fn wins_with(problem: &Problem, domain: &Domain, (name, x, y, args): &FirstMove) -> bool {
+    if name == solver::PASS {
+        return solver::solve(problem, domain).is_some();
+    }
+    let board = Board::initial(problem, domain);
+    domain.black_actions.iter()
+        .filter(|action| action.name == *name && board.actions(action).contains(&(*x, *y, args.clone())))
+        .any(|action| {
+            let mut after = board.clone();
+            after.effect_conditon(action, *x, *y, args);
+            after.assert_goals(&problem.black_goals) || solver::solve(&after.problem(1), domain).is_some()
+        })
+}
+
+/// depqbf's verdict, or `None` if it runs out of `BDDL_DIFFERENTIAL_QBF_TIMEOUT` seconds. Some
+/// random instances are hard for depqbf whatever the encoding, which says nothing about the
+/// encoding being right.
+fn check_qbf(formula: Formula) -> Option<bool> {
+    let timeout = Duration::from_secs(env("BDDL_DIFFERENTIAL_QBF_TIMEOUT", 10));
+    let mut depqbf = Command::new("depqbf")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .spawn()
+        .unwrap();
+    depqbf.stdin.take().unwrap().write_all(formula.to_qdimacs().as_bytes()).unwrap();
+    let start = Instant::now();
+    loop {
+        if let Some(status) = depqbf.try_wait().unwrap() {
+            return Some(status.code() == Some(10));
+        }
+        if start.elapsed() > timeout {
+            let _ = depqbf.kill();
+            let _ = depqbf.wait();
+            return None;
+        }
+        std::thread::sleep(Duration::from_millis(10));
+    }
+}
+
+fn disagree(problem: &Problem, domain: &Domain, options: solver_qbf::Options) -> bool {
+    let verdicts = verdicts(problem, domain, options);
+    verdicts.iter().any(|(_, v)| *v != verdicts[0].1)
+}
+
+/// Every coordinate the instance mentions lies on its board.
+fn fits(problem: &Problem, domain: &Domain) -> bool {
+    let size = problem.size;
+    let fits_e = |e: &E, max: i64| !matches!(*e, E::Int(i) if i >= max);
+    let fits_sub_condition = |s: &SubCondition| {
+        let (SubCondition::Id { x_e, y_e, .. } | SubCondition::Not { x_e, y_e, .. }) = s;
+        fits_e(x_e, size.x) && fits_e(y_e, size.y)
+    };
+    let fits_condition = |c: &Condition| c.atoms().into_iter().all(fits_sub_condition);
+    size.x >= 1 && size.y >= 1
+        && problem.init.iter().all(|p| p.x < size.x && p.y < size.y)
+        && problem.black_goals.iter().chain(&problem.white_goals).all(fits_condition)
+        && domain.black_actions.iter().chain(&domain.white_actions)
+            .all(|a| fits_condition(&a.precondition) && a.effect.iter().all(fits_sub_condition))
+}
+
+/// The conditions a generated condition is the conjunction of.
+fn conjuncts(condition: &Condition) -> &[Condition] {
+    match condition {
+        Condition::And(conditions) => conditions,
+        _ => unreachable!("generated conditions are conjunctions"),
+    }
+}
+
+fn conjuncts_mut(condition: &mut Condition) -> &mut Vec<Condition> {
+    match condition {
+        Condition::And(conditions) => conditions,
+        _ => unreachable!("generated conditions are conjunctions"),
+    }
+}
+
+/// Instances one step smaller than the given one.
+fn shrink(problem: &Problem, domain: &Domain) -> Vec<(Problem, Domain)> {
+    let mut candidates = Vec::new();
+    let mut with_problem = |f: &dyn Fn(&mut Problem)| {
+        let mut problem = problem.clone();
+        f(&mut problem);
+        candidates.push((problem, domain.clone()));
+    };
+    if problem.depth > 1 {
+        with_problem(&|p| p.depth -= 1);
+    }
+    if problem.turns != Turns::default() {
+        with_problem(&|p| p.turns = Turns::default());
+    }
+    if problem.stuck != Stuck::Loss {
+        with_problem(&|p| p.stuck = Stuck::Loss);
+    }
+    with_problem(&|p| p.size.x -= 1);
+    with_problem(&|p| p.size.y -= 1);
+    for i in 0..problem.init.len() {
+        with_problem(&|p| { p.init.remove(i); });
+    }
+    for (g, goal) in problem.black_goals.iter().enumerate() {
+        with_problem(&|p| { p.black_goals.remove(g); });
+        for s in 0..conjuncts(goal).len() {
+            with_problem(&|p| { conjuncts_mut(&mut p.black_goals[g]).remove(s); });
+        }
+    }
+    for (g, goal) in problem.white_goals.iter().enumerate() {
+        with_problem(&|p| { p.white_goals.remove(g); });
+        for s in 0..conjuncts(goal).len() {
+            with_problem(&|p| { conjuncts_mut(&mut p.white_goals[g]).remove(s); });
+        }
+    }
+
+    let mut with_actions = |f: &dyn Fn(&mut Vec<Action>)| {
+        for black in [true, false] {
+            let mut domain = domain.clone();
+            f(if black { &mut domain.black_actions } else { &mut domain.white_actions });
+            candidates.push((problem.clone(), domain));
+        }
+    };
+    for a in 0..domain.black_actions.len().max(domain.white_actions.len()) {
+        let in_range = |actions: &Vec<Action>| a < actions.len();
+        with_actions(&|actions| if in_range(actions) { actions.remove(a); });
+        for s in 1..4 {
+            // Keeps the first effect, and every precondition guarding a further effect.
+            with_actions(&|actions| if in_range(actions) && s < actions[a].effect.len() {
+                actions[a].effect.remove(s);
+            });
+            with_actions(&|actions| if in_range(actions) && s < conjuncts(&actions[a].precondition).len() {
+                let guards = match &conjuncts(&actions[a].precondition)[s] {
+                    Condition::Atom(SubCondition::Id { x_e, y_e, .. } | SubCondition::Not { x_e, y_e, .. }) => actions[a].effect.iter()
+                        .any(|e| matches!(e, SubCondition::Id { x_e: x, y_e: y, .. } | SubCondition::Not { x_e: x, y_e: y, .. } if x == x_e && y == y_e)),
+                    _ => false,
+                };
+                if !guards {
+                    conjuncts_mut(&mut actions[a].precondition).remove(s);
+                }
+            });
+        }
+    }
+    candidates.retain(|(p, d)| fits(p, d) && (p.to_string(), d.to_string()) != (problem.to_string(), domain.to_string()));
+    candidates
+}
+
+/// Greedily shrinks a disagreeing instance while it keeps disagreeing.
+fn minimise(mut problem: Problem, mut domain: Domain, options: solver_qbf::Options) -> (Problem, Domain) {
+    while let Some((p, d)) = shrink(&problem, &domain).into_iter().find(|(p, d)| disagree(p, d, options)) {
+        problem = p;
+        domain = d;
+    }
+    (problem, domain)
+}
+
+fn env<T: std::str::FromStr>(name: &str, default: T) -> T {
+    std::env::var(name).ok().and_then(|v| v.parse().ok()).unwrap_or(default)
+}
+
+/// Writes the instance to `BDDL_REPRO_DIR`, by default the temporary directory.
+fn write_reproducer(seed: u64, problem: &Problem, domain: &Domain) -> (PathBuf, PathBuf) {
+    let dir = std::env::var_os("BDDL_REPRO_DIR").map(PathBuf::from).unwrap_or_else(std::env::temp_dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    let problem_path = dir.join(format!("differential-{seed}.problem"));
+    let domain_path = dir.join(format!("differential-{seed}.domain"));
+    std::fs::write(&problem_path, problem.to_string()).unwrap();
+    std::fs::write(&domain_path, domain.to_string()).unwrap();
+    (problem_path, domain_path)
+}
+
+/// Tries `BDDL_DIFFERENTIAL_CASES` seeds from `BDDL_DIFFERENTIAL_SEED` on, and minimises the
+/// first disagreement into a reproducer.
+#[test]
+fn backends_agree() {
+    let start = env("BDDL_DIFFERENTIAL_SEED", 0u64);
+    let cases = env("BDDL_DIFFERENTIAL_CASES", 40u64);
+    for seed in start..start + cases {
+        let (problem, domain, options) = random_instance(seed);
+        if disagree(&problem, &domain, options) {
+            let (problem, domain) = minimise(problem, domain, options);
+            let (problem_path, domain_path) = write_reproducer(seed, &problem, &domain);
+            panic!(
+                "backends disagree on seed {seed}: {:?}\nreproducer: {} {}",
+                verdicts(&problem, &domain, options), problem_path.display(), domain_path.display(),
+            );
+        }
+    }
+}
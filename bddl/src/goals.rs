@@ -0,0 +1,79 @@
+use crate::bddl::{Condition, Pred, Problem, SubCondition};
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Literal {
+    /// A cell required to hold, or not to hold, a predicate.
+    Cell { x: i64, y: i64, pred: Pred, positive: bool },
+    /// A variable required to have, or not to have, a value.
+    Value { variable: String, value: i64, positive: bool },
+}
+
+/// A goal condition instantiated at a concrete anchor. Holds iff all its literals hold.
+pub type Line = Vec<Literal>;
+
+/// The lines a condition, or its negation, holds on at the anchor `(x, y)`, in disjunctive
+/// normal form. A sub condition off the board is false, so its negation holds.
+fn dnf(condition: &Condition, negated: bool, x: i64, y: i64, problem: &Problem) -> Vec<Line> {
+    let product = |conditions: &[Condition]| conditions.iter()
+        .fold(vec![Line::new()], |lines, condition| {
+            let factor = dnf(condition, negated, x, y, problem);
+            lines.iter()
+                .flat_map(|line| factor.iter().map(move |other| [line.as_slice(), other].concat()))
+                .collect()
+        });
+    let sum = |conditions: &[Condition]| conditions.iter()
+        .flat_map(|condition| dnf(condition, negated, x, y, problem))
+        .collect();
+    match condition {
+        Condition::Atom(sub_condition) => {
+            let (SubCondition::Id { pred, x_e, y_e, via } | SubCondition::Not { pred, x_e, y_e, via }) = sub_condition;
+            match problem.target(x_e, y_e, via, x, y, &[]) {
+                Some((x, y)) => vec![vec![Literal::Cell { x, y, pred: pred.clone(), positive: matches!(sub_condition, SubCondition::Id { .. }) != negated }]],
+                None if negated => vec![Line::new()],
+                None => Vec::new(),
+            }
+        },
+        Condition::Value { variable, value } => vec![vec![Literal::Value { variable: variable.clone(), value: *value, positive: !negated }]],
+        Condition::And(conditions) if !negated => product(conditions),
+        Condition::Or(conditions) if negated => product(conditions),
+        Condition::And(conditions) | Condition::Or(conditions) => sum(conditions),
+        Condition::Not(condition) => dnf(condition, !negated, x, y, problem),
+    }
+}
+
+fn instantiate(condition: &Condition, x: i64, y: i64, problem: &Problem) -> Vec<Line> {
+    // Both positive for different values, or of opposite sign for the same one.
+    let clash = |a: bool, b: bool, same: bool| a && b && !same || a != b && same;
+    let contradicts = |a: &Literal, b: &Literal| match (a, b) {
+        (Literal::Cell { x, y, pred, positive }, Literal::Cell { x: bx, y: by, pred: b_pred, positive: b_positive }) =>
+            (x, y) == (bx, by) && clash(*positive, *b_positive, pred == b_pred),
+        (Literal::Value { variable, value, positive }, Literal::Value { variable: b_variable, value: b_value, positive: b_positive }) =>
+            variable == b_variable && clash(*positive, *b_positive, value == b_value),
+        _ => false,
+    };
+    dnf(condition, false, x, y, problem).into_iter()
+        .map(|mut line| {
+            line.sort();
+            line.dedup();
+            line
+        })
+        .filter(|line| line.iter().enumerate().all(|(i, a)| line[i + 1..].iter().all(|b| !contradicts(a, b))))
+        .collect()
+}
+
+/// Expands goal conditions into the lines they hold on, without duplicates and without lines
+/// implied by a smaller one. The goals hold iff any of the lines does.
+pub fn compile(goals: &[Condition], problem: &Problem) -> Vec<Line> {
+    let mut lines: Vec<Line> = problem.cells()
+        .flat_map(|(x, y)| goals.iter().flat_map(move |condition| instantiate(condition, x, y, problem)))
+        .collect();
+    lines.sort_by(|a, b| a.len().cmp(&b.len()).then_with(|| a.cmp(b)));
+    lines.dedup();
+    let mut compiled: Vec<Line> = Vec::new();
+    for line in lines {
+        if !compiled.iter().any(|smaller| smaller.iter().all(|literal| line.contains(literal))) {
+            compiled.push(line);
+        }
+    }
+    compiled
+}
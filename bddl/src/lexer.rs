@@ -1,5 +1,7 @@
 use logos::Logos;
 
+use crate::bddl::Invalid;
+
 #[derive(Logos, Clone, Debug)]
 pub enum Token {
     #[regex(r"\s", logos::skip)]
@@ -12,6 +14,7 @@ pub enum Token {
     #[token(":")] Colon,
     #[token(",")] Comma,
     #[token("?")] QuestionMark,
+    #[token("=")] Equals,
 
     #[token("blackactions")] BlackActions,
     #[token("whiteactions")] WhiteActions,
@@ -34,7 +37,96 @@ pub enum Token {
     #[token("whitegoals")]#[token("whitegoal")] WhiteGoals,
     #[token("effect")] Effect,
     #[token("NOT")] Not,
+    #[token("AND")] And,
+    #[token("OR")] Or,
+    // Sections added to the format are single tokens, so their names stay usable as identifiers.
+    #[token("#nomove")] NoMove,
+    #[token("#positions")] Positions,
+    #[token("#neighbours")]#[token("#neighbors")] Neighbours,
+    #[token("#predicates")] Predicates,
+    #[token("#variables")] Variables,
+    #[token("#turnorder")] TurnOrder,
 
     #[regex(r"\d+", |lex| lex.slice().parse().ok())] Int(i64),
     #[regex(r"[a-zA-Z]([a-zA-Z0-9]*)", |lex| lex.slice().to_owned())] String(String),
 }
+
+/// Tokens with their byte spans, as the parser expects them.
+pub fn tokens(src: &str) -> impl Iterator<Item = Result<(usize, Token, usize), Invalid>> + '_ {
+    Token::lexer(src).spanned().map(|(token, span)| match token {
+        Ok(token) => Ok((span.start, token, span.end)),
+        Err(()) => Err(Invalid { location: span.start, reason: format!("unexpected {}", &src[span]) }),
+    })
+}
+
+/// Where and why a domain or problem failed to parse.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    pub line: usize,
+    pub column: usize,
+    /// The unexpected text, empty at the end of the input.
+    pub found: String,
+    pub expected: Vec<String>,
+    /// The `#` section the error occurred in.
+    pub section: Option<String>,
+    /// Why the grammar rejects text it could read.
+    pub reason: Option<String>,
+}
+
+impl ParseError {
+    pub fn new(src: &str, error: lalrpop_util::ParseError<usize, Token, Invalid>) -> ParseError {
+        use lalrpop_util::ParseError::*;
+        let word = |location: usize| {
+            let rest = &src[location..];
+            let first = rest.chars().next().map_or(0, char::len_utf8);
+            location + rest.find(|c: char| !c.is_alphanumeric()).unwrap_or(rest.len()).max(first)
+        };
+        let (start, end, expected, reason) = match error {
+            InvalidToken { location } => (location, word(location), Vec::new(), None),
+            User { error: Invalid { location, reason } } => (location, word(location), Vec::new(), Some(reason)),
+            UnrecognizedEof { location, expected } => (location, location, expected, None),
+            UnrecognizedToken { token: (start, _, end), expected } => (start, end, expected, None),
+            ExtraToken { token: (start, _, end) } => (start, end, Vec::new(), None),
+        };
+        // A section token is reported by its name, like the sections lexed as `#` and a word.
+        let start = if end > start + 1 && src[start..].starts_with('#') { start + 1 } else { start };
+        let before = &src[..start];
+        let line = before.matches('\n').count() + 1;
+        let column = before.len() - before.rfind('\n').map_or(0, |i| i + 1) + 1;
+        let section = before.rfind('#')
+            .map(|i| src[i + 1..].split(|c: char| !c.is_alphanumeric()).next().unwrap().to_owned())
+            .filter(|name| !name.is_empty());
+        let expected = expected.into_iter().map(|e| e.trim_matches('"').to_owned()).collect();
+        ParseError { line, column, found: src[start..end].to_owned(), expected, section, reason }
+    }
+
+    /// The construct the grammar does not support: an unknown section, or the unexpected text
+    /// within a known one.
+    pub fn construct(&self) -> String {
+        match &self.section {
+            Some(section) if self.found == *section => format!("#{}", section),
+            Some(section) => format!("#{}: {}", section, self.found_or_eof()),
+            None => self.found_or_eof().to_owned(),
+        }
+    }
+
+    fn found_or_eof(&self) -> &str {
+        if self.found.is_empty() { "end of file" } else { &self.found }
+    }
+}
+
+impl std::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match (&self.reason, &self.section) {
+            (Some(reason), Some(section)) => write!(f, "{}:{}: {} in #{}", self.line, self.column, reason, section)?,
+            (Some(reason), None) => write!(f, "{}:{}: {}", self.line, self.column, reason)?,
+            (None, Some(section)) if self.found == *section => write!(f, "{}:{}: unknown section #{}", self.line, self.column, section)?,
+            (None, Some(section)) => write!(f, "{}:{}: unexpected {} in #{}", self.line, self.column, self.found_or_eof(), section)?,
+            (None, None) => write!(f, "{}:{}: unexpected {}", self.line, self.column, self.found_or_eof())?,
+        }
+        if !self.expected.is_empty() {
+            write!(f, ", expected one of {}", self.expected.join(" "))?;
+        }
+        Ok(())
+    }
+}
@@ -1,8 +1,12 @@
 #![feature(slice_group_by)]
 
-use bddl::{Domain, Problem};
+use std::path::Path;
+
+use bddl::{resolve_init, Domain, Player, Problem};
 use lalrpop_util::lalrpop_mod;
-use logos::Logos;
+use lexer::ParseError;
+use outcome::{Outcome, Query};
+use record::Record;
 use z3::{Context, Tactic};
 
 mod bddl;
@@ -11,39 +15,272 @@ mod solver_z3;
 mod solver_qbf;
 mod lexer;
 mod qbf;
+mod position;
+mod goals;
+mod outcome;
+mod play;
+mod record;
+mod render;
+mod bench;
+#[cfg(test)]
+mod tests;
+#[cfg(test)]
+mod differential;
 
 lalrpop_mod!(parser);
 
+const USAGE: &str = "\
+usage: bddl <problem> <domain> [options]
+       bddl encode <problem> <domain> [--format smt2|qdimacs] [options]
+       bddl position <problem> <domain> [options]
+       bddl play <problem> <domain> [--as black|white] [options]
+       bddl render <problem> <domain> [--line <moves>] [--format text|svg]
+       bddl replay <record> [--at <n>] [options]
+       bddl bench <dir> [--backends <list>] [--timeout <seconds>] [--format csv|json] [--parse-only]
+
+Says whether black wins, or answers --query.
+
+options:
+  --backend z3|qbf|explicit          solver, z3 by default
+  --query black|white|noloss|draw|outcome
+  --position log|onehot|order|cell   qbf encoding of the move
+  --transition chained|frame         qbf encoding of the board after a move
+  --flatten                          qbf goals on their precompiled lines
+  --evaluate                         expand the qbf in process instead of running depqbf
+  --moves <moves>                    start after moves such as \"place 1 1, place 0 0\"
+  --board <init> --ply <n>           start from a board written like #init, after n moves
+  --report                           print the line bench reads
+
+play is a game against the backend, black for the human by default. Moves are entered as
+`action x y` followed by the values of any extra parameters.
+
+render prints the initial position, or every position of a line given like --moves, as text
+or as an SVG animation of one position after another.
+
+replay checks the moves of a record and the goals they fire. With --at it stops after n moves
+and answers from the position reached.
+
+bench runs every entry of <dir>/manifest, or without one every problem of the published
+layout below <dir>, on each backend of <list>: comma separated explicit, z3 or
+qbf[/<position>][/<transition>][/flatten], by default explicit,z3,qbf,qbf/flatten. Runs time
+out after 60 seconds by default. Sizes are QDIMACS clauses for qbf and the length of the
+SMT-LIB2 script for z3. --parse-only stops after listing the constructs that do not parse.
+";
+
 fn main() {
-    let sproblem = std::fs::read_to_string(std::env::args().nth(1).unwrap()).unwrap();
-    let sdomain = std::fs::read_to_string(std::env::args().nth(2).unwrap()).unwrap();
-    /*let problem = parse_problem(&sproblem);
-    let domain = parse_domain(&sdomain);
-    let now = std::time::Instant::now();
-    let problem = parse_problem(&sproblem);
-    let domain = parse_domain(&sdomain);
-    let now = std::time::Instant::now();
-    let formula = solver_qbf::solve(problem, domain, true);
-    println!("{}: {:?}", formula.check_with_preprocessing(), now.elapsed());*/
-    let problem = parse_problem(&sproblem);
-    let domain = parse_domain(&sdomain);
+    if std::env::args().any(|arg| arg == "--help" || arg == "-h") {
+        return print!("{}", USAGE);
+    }
+    if std::env::args().nth(1).as_deref() == Some("bench") {
+        return bench::run();
+    }
+    if std::env::args().nth(1).as_deref() == Some("play") {
+        let (problem, domain) = load(&arg(2), &arg(3));
+        let human = flag("--as").map(|player| player.parse().expect("unknown player")).unwrap_or(Player::Black);
+        let played = play::play(&problem, &domain, human, std::io::stdin().lock(), std::io::stdout(), |problem, domain| run(problem, domain, false).1);
+        if let Err(e) = played {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+    if std::env::args().nth(1).as_deref() == Some("render") {
+        let (problem, domain) = load(&arg(2), &arg(3));
+        let moves = record::parse_moves(&problem, &flag("--line").unwrap_or_default());
+        let positions = moves.and_then(|moves| render::line(&problem, &domain, &moves)).unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        });
+        match flag("--format").as_deref().unwrap_or("text") {
+            "text" => print!("{}", render::pages(&positions)),
+            "svg" if positions.len() == 1 => print!("{}", render::svg(&problem, &domain, &positions[0].1)),
+            "svg" => print!("{}", render::animation(&problem, &domain, &positions, 1)),
+            format => panic!("unknown format {}", format),
+        }
+        return;
+    }
+    if std::env::args().nth(1).as_deref() == Some("replay") {
+        return replay(&arg(2));
+    }
+    if std::env::args().nth(1).as_deref() == Some("encode") {
+        let (problem, domain) = load(&arg(2), &arg(3));
+        let problem = start(problem, &domain);
+        match flag("--format").as_deref().unwrap_or("smt2") {
+            "smt2" => {
+                let context = Context::new(&Default::default());
+                print!("{}", solver_z3::smt2(&solver_z3::solve(&context, &problem, &domain)));
+            },
+            "qdimacs" => print!("{}", solver_qbf::solve(problem, domain, qbf_options()).to_qdimacs()),
+            format => panic!("unknown format {}", format),
+        }
+        return;
+    }
+    if std::env::args().nth(1).as_deref() == Some("position") {
+        let (problem, domain) = load(&arg(2), &arg(3));
+        return print!("{}", start(problem, &domain));
+    }
+    let (problem, domain) = load(&arg(1), &arg(2));
+    answer(&start(problem, &domain), &domain);
+}
+
+/// The problem starting from the position reached by `--moves`, comma separated moves such as
+/// `place 1 1, place 0 0`, or from the board given by `--board` like `#init` once `--ply` moves
+/// have been played.
+fn start(problem: Problem, domain: &Domain) -> Problem {
+    let started = match (flag("--moves"), flag("--board")) {
+        (None, None) => return problem,
+        (Some(moves), None) => record::parse_moves(&problem, &moves).and_then(|moves| record::position(&problem, domain, &moves)),
+        (None, Some(board)) => {
+            let ply: u64 = flag("--ply").map_or(0, |ply| ply.parse().expect("number of moves"));
+            let position = parser::PositionParser::new().parse(lexer::tokens(&board))
+                .and_then(|init| resolve_init(&problem.positions, init).map_err(|error| lalrpop_util::ParseError::User { error }))
+                .map_err(|e| format!("--board:{}", ParseError::new(&board, e)));
+            position.and_then(|(init, values)| problem.with_board(init, values, ply).map_err(|e| format!("--ply: {}", e)))
+        },
+        (Some(_), Some(_)) => Err("--moves and --board exclude each other".to_owned()),
+    };
+    let started = started.and_then(|started| domain.check(&started).map(|()| started));
+    started.unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    })
+}
+
+/// Answers `--query` on the problem and prints the answer with the time it took, or the line
+/// `bench` reads with `--report`.
+fn answer(problem: &Problem, domain: &Domain) {
+    let report = std::env::args().any(|arg| arg == "--report");
     let now = std::time::Instant::now();
-    let context = Context::new(&Default::default());
-    let z3 = solver_z3::solve(&problem, &domain);
-    let formula = z3(&context);
-    let solver = Tactic::new(&context, "simplify").and_then(&Tactic::new(&context, "smt")).solver();
-    solver.assert(&formula);
-    println!("{:?}: {:?}", solver.check(), now.elapsed());
+    let (shown, won, size) = match flag("--query").as_deref() {
+        Some("outcome") => {
+            let outcome = outcome::classify(problem, domain, |problem, domain| run(problem, domain, false).1);
+            (outcome.map_or("unknown".to_owned(), |outcome| outcome.to_string()), outcome.map(|outcome| outcome == Outcome::BlackWins), None)
+        },
+        query => match query.map(|query| query.parse().expect("unknown query")).unwrap_or_default() {
+            Query::BlackWins => run(problem, domain, report),
+            query => {
+                let answer = query.answer(problem, domain, |problem, domain| run(problem, domain, false).1);
+                (answer.map_or("unknown".to_owned(), |answer| answer.to_string()), answer, None)
+            },
+        },
+    };
+    if report {
+        println!("{}", bench::report_line(won, size, now.elapsed()));
+    } else {
+        println!("{}: {:?}", shown, now.elapsed());
+    }
 }
 
-fn parse_domain(src: &str) -> Domain {
-    let lexer = lexer::Token::lexer(src);
+/// Replays the record at `path`, see `record`.
+fn replay(path: &str) {
+    let record: Record = std::fs::read_to_string(path)
+        .unwrap_or_else(|e| panic!("cannot read {}: {}", path, e))
+        .parse()
+        .unwrap_or_else(|e| {
+            eprintln!("{}: {}", path, e);
+            std::process::exit(2);
+        });
+    let dir = Path::new(path).parent().unwrap_or(Path::new(""));
+    let (problem, domain) = load(&dir.join(&record.problem).to_string_lossy(), &dir.join(&record.domain).to_string_lossy());
+    let at = flag("--at").map(|at| at.parse::<usize>().expect("number of moves"));
+    let moves = record.moves.get(..at.unwrap_or(record.moves.len())).unwrap_or_else(|| {
+        eprintln!("{}: the record has {} moves", path, record.moves.len());
+        std::process::exit(1);
+    });
+    let (lines, board) = record::replay(&problem, &domain, moves).unwrap_or_else(|e| {
+        eprintln!("{}: {}", path, e);
+        std::process::exit(1);
+    });
+    lines.iter().for_each(|line| println!("{}", line));
+    if at.is_some() {
+        print!("{}", board);
+        answer(&board.problem(moves.len() as u64), &domain);
+    }
+}
+
+/// Whether black wins on the backend given with `--backend`, with the result as shown and
+/// the size of the formula if `size` is set or it comes for free.
+fn run(problem: &Problem, domain: &Domain, size: bool) -> (String, Option<bool>, Option<usize>) {
+    match flag("--backend").as_deref().unwrap_or("z3") {
+        "z3" => {
+            let context = Context::new(&Default::default());
+            let size = size.then(|| solver_z3::smt2(&solver_z3::solve(&context, problem, domain)).len());
+            let solver = Tactic::new(&context, "simplify").and_then(&Tactic::new(&context, "smt")).solver();
+            match solver_z3::solve_move(problem, domain, Some(&solver)) {
+                Ok(Some(first)) => (format!("Sat {:?}", first), Some(true), size),
+                Ok(None) => ("Unsat".to_owned(), Some(false), size),
+                Err(_) => ("Unknown".to_owned(), None, size),
+            }
+        },
+        "qbf" => {
+            let formula = solver_qbf::solve(problem.clone(), domain.clone(), qbf_options());
+            // In-process expansion instead of bloqqer and depqbf, only feasible for tiny instances.
+            if std::env::args().any(|arg| arg == "--evaluate") {
+                let result = formula.evaluate();
+                (result.to_string(), Some(result), None)
+            } else {
+                let cnf = formula.to_qdimacs();
+                let size = bench::qdimacs_clauses(&cnf);
+                let result = qbf::check_qdimacs_with_preprocessing(&cnf);
+                (result.to_string(), Some(result), Some(size))
+            }
+        },
+        "explicit" => {
+            let result = solver::solve(problem, domain);
+            (format!("{:?}", result), Some(result.is_some()), None)
+        },
+        backend => panic!("unknown backend {}", backend),
+    }
+}
+
+/// The encoding chosen with `--flatten`, `--position` and `--transition`.
+fn qbf_options() -> solver_qbf::Options {
+    solver_qbf::Options {
+        flatten: std::env::args().any(|arg| arg == "--flatten"),
+        position: flag("--position").map(|p| p.parse().expect("unknown position encoding")).unwrap_or_default(),
+        transition: flag("--transition").map(|t| t.parse().expect("unknown transition encoding")).unwrap_or_default(),
+    }
+}
+
+/// Command line argument `index`.
+fn arg(index: usize) -> String {
+    std::env::args().nth(index).unwrap_or_else(|| panic!("missing argument {}", index))
+}
+
+/// Reads a problem and a domain, exiting if either does not parse or the domain does not
+/// declare what the problem uses.
+fn load(problem_path: &str, domain_path: &str) -> (Problem, Domain) {
+    let problem = read(problem_path, parse_problem);
+    let domain = read(domain_path, parse_domain);
+    if let Err(e) = domain.check(&problem) {
+        eprintln!("{}: {}", problem_path, e);
+        std::process::exit(2);
+    }
+    (problem, domain)
+}
+
+/// Value following `name` on the command line.
+fn flag(name: &str) -> Option<String> {
+    let mut args = std::env::args().skip_while(|arg| arg != name);
+    args.next()?;
+    args.next()
+}
+
+fn parse_domain(src: &str) -> Result<Domain, ParseError> {
     let parser = parser::DomainParser::new();
-    parser.parse(lexer).unwrap()
+    parser.parse(lexer::tokens(src)).map_err(|e| ParseError::new(src, e))
 }
 
-fn parse_problem(src: &str) -> Problem {
-    let lexer = lexer::Token::lexer(src);
+fn parse_problem(src: &str) -> Result<Problem, ParseError> {
     let parser = parser::ProblemParser::new();
-    parser.parse(lexer).unwrap()
+    parser.parse(lexer::tokens(src)).map_err(|e| ParseError::new(src, e))
+}
+
+/// Reads and parses a file, exiting with the location of any syntax error.
+fn read<T>(path: &str, parse: fn(&str) -> Result<T, ParseError>) -> T {
+    let src = std::fs::read_to_string(path).unwrap_or_else(|e| panic!("cannot read {}: {}", path, e));
+    parse(&src).unwrap_or_else(|e| {
+        eprintln!("{}:{}", path, e);
+        std::process::exit(2);
+    })
 }
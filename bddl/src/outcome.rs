@@ -0,0 +1,74 @@
+//! Questions about a game besides whether black wins. Each is answered by asking whether black
+//! wins the game, or the game with the players swapped.
+
+use std::{fmt::{self, Display, Formatter}, str::FromStr};
+
+use crate::bddl::{Domain, Problem};
+
+/// The result of perfect play within the depth.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    BlackWins,
+    WhiteWins,
+    /// Neither player can force a win.
+    Draw,
+}
+
+/// A question about the outcome, given with `--query`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Query {
+    /// Black has a forced win.
+    #[default]
+    BlackWins,
+    /// White has a forced win.
+    WhiteWins,
+    /// Black can at least force a draw.
+    NoLoss,
+    /// The outcome is a draw.
+    Draw,
+}
+
+impl FromStr for Query {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "black" => Ok(Query::BlackWins),
+            "white" => Ok(Query::WhiteWins),
+            "noloss" => Ok(Query::NoLoss),
+            "draw" => Ok(Query::Draw),
+            _ => Err(()),
+        }
+    }
+}
+
+impl Query {
+    /// Answers the query with `black_wins`, which tells whether black has a forced win, or
+    /// gives up with `None`.
+    pub fn answer(self, problem: &Problem, domain: &Domain, mut black_wins: impl FnMut(&Problem, &Domain) -> Option<bool>) -> Option<bool> {
+        match self {
+            Query::BlackWins => black_wins(problem, domain),
+            Query::WhiteWins => black_wins(&problem.swapped(), &domain.swapped()),
+            Query::NoLoss => black_wins(&problem.swapped(), &domain.swapped()).map(|won| !won),
+            Query::Draw => classify(problem, domain, black_wins).map(|outcome| outcome == Outcome::Draw),
+        }
+    }
+}
+
+/// The outcome of the game, with `black_wins` as in `Query::answer`.
+pub fn classify(problem: &Problem, domain: &Domain, mut black_wins: impl FnMut(&Problem, &Domain) -> Option<bool>) -> Option<Outcome> {
+    if black_wins(problem, domain)? {
+        return Some(Outcome::BlackWins);
+    }
+    Some(if black_wins(&problem.swapped(), &domain.swapped())? { Outcome::WhiteWins } else { Outcome::Draw })
+}
+
+impl Display for Outcome {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Outcome::BlackWins => write!(f, "black wins"),
+            Outcome::WhiteWins => write!(f, "white wins"),
+            Outcome::Draw => write!(f, "draw"),
+        }
+    }
+}
@@ -0,0 +1,100 @@
+//! A game on the terminal against a backend.
+
+use std::io::{self, BufRead, Write};
+
+use crate::{bddl::{Action, Domain, Player, Problem, Stuck}, outcome::Query, solver::Board};
+
+/// An applicable action with its anchor and extra parameter values.
+type Move<'a> = (&'a Action, i64, i64, Vec<i64>);
+
+/// Plays the problem with a human as `human`, reading moves from `input`. The other player
+/// takes the first move after which `black_wins`, as in `Query::answer`, says it has a forced
+/// win, or else the first applicable one. Returns the winner, if any.
+pub fn play(
+    problem: &Problem,
+    domain: &Domain,
+    human: Player,
+    mut input: impl BufRead,
+    mut output: impl Write,
+    mut black_wins: impl FnMut(&Problem, &Domain) -> Option<bool>,
+) -> io::Result<Option<Player>> {
+    let mut board = Board::initial(problem, domain);
+    for ply in 0..problem.depth {
+        writeln!(output, "{board}")?;
+        let player = problem.turns.mover(ply);
+        let moves: Vec<Move> = domain.actions(player).iter()
+            .flat_map(|action| board.actions(action).into_iter().map(move |(x, y, args)| (action, x, y, args)))
+            .collect();
+        if moves.is_empty() {
+            match problem.stuck {
+                Stuck::Loss => {
+                    writeln!(output, "{player} cannot move and loses")?;
+                    return Ok(Some(player.opponent()));
+                },
+                Stuck::Draw => {
+                    writeln!(output, "{player} cannot move, the game is drawn")?;
+                    return Ok(None);
+                },
+                Stuck::Pass => writeln!(output, "{player} passes")?,
+            }
+        } else {
+            let (action, x, y, args) = if player == human {
+                read_move(player, &moves, &mut input, &mut output)?
+            } else {
+                let query = match player {
+                    Player::Black => Query::BlackWins,
+                    Player::White => Query::WhiteWins,
+                };
+                moves.iter()
+                    .find(|(action, x, y, args)| {
+                        let mut after = board.clone();
+                        after.effect_conditon(action, *x, *y, args);
+                        after.assert_goals(problem.goals(player))
+                            || query.answer(&after.problem(ply + 1), domain, &mut black_wins) == Some(true)
+                    })
+                    .unwrap_or(&moves[0])
+            };
+            writeln!(output, "{player} plays {}", notation(action, *x, *y, args))?;
+            board.effect_conditon(action, *x, *y, args);
+        }
+        if board.assert_goals(problem.goals(player)) {
+            writeln!(output, "{board}")?;
+            writeln!(output, "{player} wins")?;
+            return Ok(Some(player));
+        }
+    }
+    writeln!(output, "{board}")?;
+    writeln!(output, "the depth is reached, the game is drawn")?;
+    Ok(None)
+}
+
+/// Prompts until a line names one of the moves.
+fn read_move<'m, 'a>(player: Player, moves: &'m [Move<'a>], input: &mut impl BufRead, output: &mut impl Write) -> io::Result<&'m Move<'a>> {
+    loop {
+        write!(output, "{player}> ")?;
+        output.flush()?;
+        let mut line = String::new();
+        if input.read_line(&mut line)? == 0 {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "the game was left unfinished"));
+        }
+        let mut words = line.split_whitespace();
+        let name = words.next().unwrap_or_default();
+        let numbers = words.map(str::parse).collect::<Result<Vec<i64>, _>>().unwrap_or_default();
+        let found = moves.iter().find(|(action, x, y, args)| {
+            action.name == name && numbers.len() == 2 + args.len() && numbers[..2] == [*x, *y] && numbers[2..] == args[..]
+        });
+        match found {
+            Some(found) => return Ok(found),
+            None => {
+                let legal = moves.iter().map(|(action, x, y, args)| notation(action, *x, *y, args)).collect::<Vec<_>>();
+                writeln!(output, "not an applicable move, try one of: {}", legal.join(", "))?;
+            },
+        }
+    }
+}
+
+fn notation(action: &Action, x: i64, y: i64, args: &[i64]) -> String {
+    let mut notation = format!("{} {} {}", action.name, x, y);
+    args.iter().for_each(|arg| notation.push_str(&format!(" {arg}")));
+    notation
+}
@@ -0,0 +1,267 @@
+use std::str::FromStr;
+
+use crate::{qbf::{BitVector, Formula, Atom, atom}, bddl::{E, Size}};
+
+/// How the anchor cell of a move is represented by atoms.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PositionEncoding {
+    /// A binary number per axis.
+    #[default]
+    Log,
+    /// An atom per cell of which exactly one is true.
+    OneHot,
+    /// A unary number per axis, where atom `k` is true iff the coordinate is greater than `k`.
+    Order,
+    /// A single binary number holding the cell index `x * size.y + y`.
+    CellIndex,
+}
+
+impl FromStr for PositionEncoding {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "log" => Ok(PositionEncoding::Log),
+            "onehot" => Ok(PositionEncoding::OneHot),
+            "order" => Ok(PositionEncoding::Order),
+            "cell" => Ok(PositionEncoding::CellIndex),
+            _ => Err(()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Axis {
+    X,
+    Y,
+}
+
+/// Where the anchor has to be on one axis for an offset to land on a given coordinate.
+enum Target {
+    Never,
+    Always,
+    At(i64),
+    /// The extra parameter has to have the value.
+    Param(usize, i64),
+}
+
+fn target(e: &E, v: i64, max: i64, extent: i64) -> Target {
+    match *e {
+        E::Add(i) => if v - i >= 0 { Target::At(v - i) } else { Target::Never },
+        E::Sub(i) => if v + i < max { Target::At(v + i) } else { Target::Never },
+        E::Int(i) => if i == v { Target::Always } else { Target::Never },
+        E::Identity => Target::At(v),
+        E::Min => if v == 0 { Target::Always } else { Target::Never },
+        E::Max => if v == max - 1 { Target::Always } else { Target::Never },
+        E::Param { index, offset, .. } => if (0..extent).contains(&(v - offset)) { Target::Param(index, v - offset) } else { Target::Never },
+    }
+}
+
+enum Symbols {
+    Log { x: BitVector, y: BitVector },
+    OneHot(Vec<Vec<Atom>>),
+    Order { x: Vec<Atom>, y: Vec<Atom> },
+    CellIndex(BitVector),
+}
+
+/// The symbolic anchor `(?x, ?y)` of a move, with its extra parameters.
+pub struct Position {
+    truth: Atom,
+    size: Size,
+    symbols: Symbols,
+    /// A binary number per extra parameter, whatever the encoding of the anchor.
+    parameters: Vec<BitVector>,
+}
+
+impl Position {
+    pub fn new(encoding: PositionEncoding, size: Size, parameters: usize, truth: Atom) -> Position {
+        let bits = |n: i64| (2 * n - 1).ilog2().max(1) as usize;
+        let parameters = (0..parameters).map(|_| BitVector::new(bits(size.extent()))).collect();
+        let symbols = match encoding {
+            PositionEncoding::Log => Symbols::Log { x: BitVector::new(bits(size.x)), y: BitVector::new(bits(size.y)) },
+            PositionEncoding::OneHot => Symbols::OneHot((0..size.x).map(|_| (0..size.y).map(|_| atom()).collect()).collect()),
+            PositionEncoding::Order => Symbols::Order {
+                x: (1..size.x).map(|_| atom()).collect(),
+                y: (1..size.y).map(|_| atom()).collect(),
+            },
+            PositionEncoding::CellIndex => Symbols::CellIndex(BitVector::new(bits(size.x * size.y))),
+        };
+        Position { truth, size, symbols, parameters }
+    }
+
+    fn top(&self) -> Formula {
+        !!self.truth
+    }
+
+    fn bottom(&self) -> Formula {
+        !self.truth
+    }
+
+    fn max(&self, axis: Axis) -> i64 {
+        match axis {
+            Axis::X => self.size.x,
+            Axis::Y => self.size.y,
+        }
+    }
+
+    pub fn any(&self, formulas: impl Iterator<Item = Formula>) -> Formula {
+        formulas.reduce(|a, b| a | b).unwrap_or(self.bottom())
+    }
+
+    fn all(&self, formulas: impl Iterator<Item = Formula>) -> Formula {
+        formulas.reduce(|a, b| a & b).unwrap_or(self.top())
+    }
+
+    /// Constraint that the atoms describe a cell on the board and parameters in range.
+    pub fn valid(&self) -> Formula {
+        let parameters = self.all(self.parameters.iter().map(|p| p.le(self.size.extent() as u64 - 1)));
+        parameters & match &self.symbols {
+            Symbols::Log { x, y } => x.le(self.size.x as u64 - 1) & y.le(self.size.y as u64 - 1),
+            Symbols::OneHot(cells) => {
+                let atoms: Vec<Atom> = cells.iter().flatten().copied().collect();
+                let at_least_one = self.any(atoms.iter().map(|a| !!*a));
+                let at_most_one = self.all(atoms.iter().enumerate()
+                    .flat_map(|(i, a)| atoms[i + 1..].iter().map(move |b| !*a | !*b)));
+                at_least_one & at_most_one
+            },
+            Symbols::Order { x, y } => self.all(x.windows(2).chain(y.windows(2)).map(|w| (!!w[1]).implies(!!w[0]))),
+            Symbols::CellIndex(index) => index.le((self.size.x * self.size.y) as u64 - 1),
+        }
+    }
+
+    /// The extra parameters start with the values `args`.
+    pub fn args_are(&self, args: &[i64]) -> Formula {
+        self.all(args.iter().zip(&self.parameters).map(|(v, p)| p.equal(*v as u64)))
+    }
+
+    /// The extra parameter `index` is in `lo..=hi`.
+    fn param_between(&self, index: usize, lo: i64, hi: i64) -> Formula {
+        let extent = self.size.extent();
+        if hi < lo.max(0) || lo >= extent {
+            return self.bottom();
+        }
+        let lower = if lo > 0 { self.parameters[index].ge(lo as u64) } else { self.top() };
+        let upper = if hi < extent - 1 { self.parameters[index].le(hi as u64) } else { self.top() };
+        lower & upper
+    }
+
+    /// The anchor is the cell `(x, y)`.
+    pub fn at(&self, x: i64, y: i64) -> Formula {
+        match &self.symbols {
+            Symbols::OneHot(cells) => !!cells[x as usize][y as usize],
+            Symbols::CellIndex(index) => index.equal((x * self.size.y + y) as u64),
+            _ => self.axis_eq(Axis::X, x) & self.axis_eq(Axis::Y, y),
+        }
+    }
+
+    fn axis_eq(&self, axis: Axis, v: i64) -> Formula {
+        match (&self.symbols, axis) {
+            (Symbols::Log { x, .. }, Axis::X) => x.equal(v as u64),
+            (Symbols::Log { y, .. }, Axis::Y) => y.equal(v as u64),
+            (Symbols::Order { x, .. }, Axis::X) => self.order_eq(x, v),
+            (Symbols::Order { y, .. }, Axis::Y) => self.order_eq(y, v),
+            (_, Axis::X) => self.any((0..self.size.y).map(|y| self.at(v, y))),
+            (_, Axis::Y) => self.any((0..self.size.x).map(|x| self.at(x, v))),
+        }
+    }
+
+    fn axis_le(&self, axis: Axis, v: i64) -> Formula {
+        if v < 0 {
+            return self.bottom();
+        }
+        if v >= self.max(axis) - 1 {
+            return self.top();
+        }
+        match (&self.symbols, axis) {
+            (Symbols::Log { x, .. }, Axis::X) => x.le(v as u64),
+            (Symbols::Log { y, .. }, Axis::Y) => y.le(v as u64),
+            (Symbols::Order { x, .. }, Axis::X) => !x[v as usize],
+            (Symbols::Order { y, .. }, Axis::Y) => !y[v as usize],
+            (Symbols::CellIndex(index), Axis::X) => index.le(((v + 1) * self.size.y - 1) as u64),
+            _ => self.any((0..=v).map(|u| self.axis_eq(axis, u))),
+        }
+    }
+
+    fn axis_ge(&self, axis: Axis, v: i64) -> Formula {
+        if v <= 0 {
+            return self.top();
+        }
+        if v >= self.max(axis) {
+            return self.bottom();
+        }
+        match (&self.symbols, axis) {
+            (Symbols::Log { x, .. }, Axis::X) => x.ge(v as u64),
+            (Symbols::Log { y, .. }, Axis::Y) => y.ge(v as u64),
+            (Symbols::Order { x, .. }, Axis::X) => !!x[v as usize - 1],
+            (Symbols::Order { y, .. }, Axis::Y) => !!y[v as usize - 1],
+            (Symbols::CellIndex(index), Axis::X) => index.ge((v * self.size.y) as u64),
+            _ => self.any((v..self.max(axis)).map(|u| self.axis_eq(axis, u))),
+        }
+    }
+
+    fn order_eq(&self, atoms: &[Atom], v: i64) -> Formula {
+        let lower = if v > 0 { !!atoms[v as usize - 1] } else { self.top() };
+        let upper = if (v as usize) < atoms.len() { !atoms[v as usize] } else { self.top() };
+        lower & upper
+    }
+
+    fn axis_in_bounds(&self, axis: Axis, e: &E) -> Formula {
+        let max = self.max(axis);
+        match *e {
+            E::Add(i) => self.axis_le(axis, max - i - 1),
+            E::Sub(i) => self.axis_ge(axis, i),
+            E::Int(i) => if 0 <= i && i < max { self.top() } else { self.bottom() },
+            E::Identity | E::Min | E::Max => self.top(),
+            E::Param { index, offset, .. } => self.param_between(index, -offset, max - 1 - offset),
+        }
+    }
+
+    /// The anchor moved by `(x_e, y_e)` is on the board.
+    pub fn in_bounds(&self, x_e: &E, y_e: &E) -> Formula {
+        self.axis_in_bounds(Axis::X, x_e) & self.axis_in_bounds(Axis::Y, y_e)
+    }
+
+    /// Whether any anchor moved by `(x_e, y_e)` can be the cell `(x, y)`.
+    pub fn reaches(&self, x_e: &E, y_e: &E, x: i64, y: i64) -> bool {
+        let extent = self.size.extent();
+        !matches!(target(x_e, x, self.size.x, extent), Target::Never) && !matches!(target(y_e, y, self.size.y, extent), Target::Never)
+    }
+
+    /// The anchor moved by `(x_e, y_e)` is the cell `(x, y)`.
+    pub fn offset_is(&self, x_e: &E, y_e: &E, x: i64, y: i64) -> Formula {
+        let extent = self.size.extent();
+        match (target(x_e, x, self.size.x, extent), target(y_e, y, self.size.y, extent)) {
+            (Target::Never, _) | (_, Target::Never) => self.bottom(),
+            (Target::At(x), Target::At(y)) => self.at(x, y),
+            (x, y) => self.axis_target(Axis::X, x) & self.axis_target(Axis::Y, y),
+        }
+    }
+
+    fn axis_target(&self, axis: Axis, target: Target) -> Formula {
+        match target {
+            Target::Never => self.bottom(),
+            Target::Always => self.top(),
+            Target::At(v) => self.axis_eq(axis, v),
+            Target::Param(index, v) => self.parameters[index].equal(v as u64),
+        }
+    }
+
+    fn atoms(&self) -> Vec<Atom> {
+        let mut atoms: Vec<Atom> = match &self.symbols {
+            Symbols::Log { x, y } => x.bits.iter().chain(&y.bits).copied().collect(),
+            Symbols::OneHot(cells) => cells.iter().flatten().copied().collect(),
+            Symbols::Order { x, y } => x.iter().chain(y).copied().collect(),
+            Symbols::CellIndex(index) => index.bits.clone(),
+        };
+        atoms.extend(self.parameters.iter().flat_map(|p| p.bits.iter().copied()));
+        atoms
+    }
+
+    pub fn exists(&self, formula: Formula) -> Formula {
+        self.atoms().into_iter().fold(formula, |acc, atom| atom.exists(acc))
+    }
+
+    pub fn forall(&self, formula: Formula) -> Formula {
+        self.atoms().into_iter().fold(formula, |acc, atom| atom.forall(acc))
+    }
+}
@@ -1,4 +1,4 @@
-use std::{ops::{BitOr, BitAnd, Not}, cell::Cell, fmt::{Display, Write}, collections::HashMap, iter::repeat_with, process::Stdio, io::Write as _};
+use std::{ops::{BitOr, BitAnd, Not}, cell::Cell, fmt::{Display, Write}, collections::{HashMap, BTreeSet}, iter::repeat_with, process::Stdio, io::Write as _};
 
 thread_local! {
     static COUNT: Cell<i64> = Cell::new(0);
@@ -9,7 +9,7 @@ pub fn atom() -> Atom {
     Atom(COUNT.get())
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct Atom(i64);
 
 impl Not for Atom {
@@ -131,7 +131,86 @@ pub fn qdimacs(quantifiers: &Vec<(Quantifier, Atom)>, matrix: &Vec<Vec<Atom>>) -
     format!("p cnf {} {}\n{}", counter, matrix.len(), acc)
 }
 
+/// Decides formulas by expanding quantifiers. A quantified subformula is evaluated once per
+/// assignment of the atoms occurring free in it.
+struct Evaluator {
+    values: HashMap<i64, bool>,
+    free: HashMap<*const Formula, Vec<i64>>,
+    memo: HashMap<(*const Formula, Vec<bool>), bool>,
+}
+
+impl Evaluator {
+    fn free_atoms(&mut self, formula: &Formula) -> BTreeSet<i64> {
+        match formula {
+            Formula::Atom(a) => BTreeSet::from([a.0.abs()]),
+            Formula::Not(f) => self.free_atoms(f),
+            Formula::Exists(a, f) | Formula::Forall(a, f) => {
+                let mut free = self.free_atoms(f);
+                free.remove(&a.0.abs());
+                self.free.insert(formula, free.iter().copied().collect());
+                free
+            },
+            Formula::And(a, b) | Formula::Or(a, b) => {
+                let mut free = self.free_atoms(a);
+                free.extend(self.free_atoms(b));
+                free
+            },
+        }
+    }
+
+    fn quantified(&mut self, formula: &Formula, a: Atom, f: &Formula, exists: bool) -> bool {
+        let key = (formula as *const Formula, self.free[&(formula as *const Formula)].iter().map(|atom| self.values[atom]).collect());
+        if let Some(&value) = self.memo.get(&key) {
+            return value;
+        }
+        let outer = self.values.get(&a.0.abs()).copied();
+        let mut branch = |value| {
+            self.values.insert(a.0.abs(), value);
+            self.eval(f)
+        };
+        let value = if exists { branch(false) || branch(true) } else { branch(false) && branch(true) };
+        match outer {
+            Some(outer) => self.values.insert(a.0.abs(), outer),
+            None => self.values.remove(&a.0.abs()),
+        };
+        self.memo.insert(key, value);
+        value
+    }
+
+    fn eval(&mut self, formula: &Formula) -> bool {
+        match formula {
+            Formula::Atom(a) => {
+                let value = *self.values.get(&a.0.abs()).unwrap_or_else(|| panic!("atom {} has no value", a.0.abs()));
+                value == (a.0 > 0)
+            },
+            Formula::Not(f) => !self.eval(f),
+            Formula::Exists(a, f) => self.quantified(formula, *a, f, true),
+            Formula::Forall(a, f) => self.quantified(formula, *a, f, false),
+            Formula::And(a, b) => self.eval(a) && self.eval(b),
+            Formula::Or(a, b) => self.eval(a) || self.eval(b),
+        }
+    }
+}
+
 impl Formula {
+    /// Truth value of a closed formula. Exponential in the number of quantified atoms, meant
+    /// for small formulas in tests and debugging.
+    pub fn evaluate(&self) -> bool {
+        self.eval_under(&HashMap::new())
+    }
+
+    /// Truth value of the formula with its free atoms set by `assignment`. A negated atom as
+    /// key sets its atom to the opposite value. Panics if a free atom has no value.
+    pub fn eval_under(&self, assignment: &HashMap<Atom, bool>) -> bool {
+        let mut evaluator = Evaluator {
+            values: assignment.iter().map(|(a, &value)| (a.0.abs(), value == (a.0 > 0))).collect(),
+            free: HashMap::new(),
+            memo: HashMap::new(),
+        };
+        evaluator.free_atoms(self);
+        evaluator.eval(self)
+    }
+
     /// Propogates negation such that they are only at atom level
     pub fn denegify(self) -> Self {
         match self {
@@ -155,9 +234,11 @@ impl Formula {
         }
     }
 
+    /// Pulls all quantifiers to the front. Every quantified atom is renamed to a fresh one,
+    /// so atoms quantified more than once, or also occurring free, are not captured.
     pub fn prenexify(self) -> Formula {
         let mut prenex = Vec::new();
-        let mut ret = self.prenexify_inner(&mut prenex);
+        let mut ret = self.prenexify_inner(&mut prenex, &mut HashMap::new());
         for (is_exists, atom) in prenex.into_iter().rev() {
             ret = if is_exists {
                 Self::Exists(atom, Box::new(ret))
@@ -169,23 +250,33 @@ impl Formula {
         ret
     }
 
-    fn prenexify_inner(self, prenex: &mut Vec<(bool, Atom)>) -> Formula {
+    fn prenexify_inner(self, prenex: &mut Vec<(bool, Atom)>, renamed: &mut HashMap<i64, Atom>) -> Formula {
         match self {
-            Formula::Atom(v) => Formula::Atom(v),
-            Formula::Not(_) => panic!("Cannot prenex with not. denegify must be called first."),
-            Formula::Exists(a, f) => {
-                prenex.push((true, a));
-                f.prenexify_inner(prenex)
-            },
-            Formula::Forall(a, f) => {
-                prenex.push((false, a));
-                f.prenexify_inner(prenex)
+            Formula::Atom(v) => match renamed.get(&v.0.abs()) {
+                Some(&fresh) if v.0 < 0 => Formula::Atom(fresh.invert()),
+                Some(&fresh) => Formula::Atom(fresh),
+                None => Formula::Atom(v),
             },
-            Formula::And(a, b) => Formula::And(Box::new(a.prenexify_inner(prenex)), Box::new(b.prenexify_inner(prenex))),
-            Formula::Or(a, b) => Formula::Or(Box::new(a.prenexify_inner(prenex)), Box::new(b.prenexify_inner(prenex))),
+            Formula::Not(_) => panic!("Cannot prenex with not. denegify must be called first."),
+            Formula::Exists(a, f) => Self::prenexify_quantifier(true, a, *f, prenex, renamed),
+            Formula::Forall(a, f) => Self::prenexify_quantifier(false, a, *f, prenex, renamed),
+            Formula::And(a, b) => Formula::And(Box::new(a.prenexify_inner(prenex, renamed)), Box::new(b.prenexify_inner(prenex, renamed))),
+            Formula::Or(a, b) => Formula::Or(Box::new(a.prenexify_inner(prenex, renamed)), Box::new(b.prenexify_inner(prenex, renamed))),
         }
     }
 
+    fn prenexify_quantifier(is_exists: bool, a: Atom, f: Formula, prenex: &mut Vec<(bool, Atom)>, renamed: &mut HashMap<i64, Atom>) -> Formula {
+        let fresh = atom();
+        prenex.push((is_exists, fresh));
+        let outer = renamed.insert(a.0.abs(), fresh);
+        let ret = f.prenexify_inner(prenex, renamed);
+        match outer {
+            Some(outer) => renamed.insert(a.0.abs(), outer),
+            None => renamed.remove(&a.0.abs()),
+        };
+        ret
+    }
+
     pub fn implies(self, other: Self) -> Self {
         !self | other
     }
@@ -247,31 +338,14 @@ impl Formula {
         }
     }
 
-    pub fn check_with_preprocessing(self) -> bool {
+    /// The formula in prenex CNF as QDIMACS text.
+    pub fn to_qdimacs(self) -> String {
         let (atoms, clauses) = self.denegify().prenexify().prenex_to_prenex_cnf();
-        let cnf = qdimacs(&atoms, &clauses);
-        let mut bloqqer = std::process::Command::new("bloqqer")
-            .stdin(Stdio::piped())
-            .stdout(Stdio::piped())
-            .spawn()
-            .unwrap();
-        let bloqqer_stdin = bloqqer.stdin.as_mut().unwrap();
-        bloqqer_stdin.write_all(cnf.as_bytes()).unwrap();
-        let mut depqbf = std::process::Command::new("depqbf")
-            .stdin(Stdio::piped())
-            .stdout(Stdio::null())
-            .spawn()
-            .unwrap();
-        let bloqqer_stdout = bloqqer.wait_with_output().unwrap().stdout;
-        let stdin = depqbf.stdin.as_mut().unwrap();
-        stdin.write_all(&bloqqer_stdout).unwrap();
-        let exit = depqbf.wait().unwrap();
-        exit.code() == Some(10)
+        qdimacs(&atoms, &clauses)
     }
 
     pub fn check(self) -> bool {
-        let (atoms, clauses) = self.denegify().prenexify().prenex_to_prenex_cnf();
-        let cnf = qdimacs(&atoms, &clauses);
+        let cnf = self.to_qdimacs();
         let mut depqbf = std::process::Command::new("depqbf")
             .stdin(Stdio::piped())
             .stdout(Stdio::null())
@@ -284,6 +358,27 @@ impl Formula {
     }
 }
 
+/// Decides QDIMACS text with bloqqer and depqbf.
+pub fn check_qdimacs_with_preprocessing(cnf: &str) -> bool {
+    let mut bloqqer = std::process::Command::new("bloqqer")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let bloqqer_stdin = bloqqer.stdin.as_mut().unwrap();
+    bloqqer_stdin.write_all(cnf.as_bytes()).unwrap();
+    let mut depqbf = std::process::Command::new("depqbf")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .spawn()
+        .unwrap();
+    let bloqqer_stdout = bloqqer.wait_with_output().unwrap().stdout;
+    let stdin = depqbf.stdin.as_mut().unwrap();
+    stdin.write_all(&bloqqer_stdout).unwrap();
+    let exit = depqbf.wait().unwrap();
+    exit.code() == Some(10)
+}
+
 impl BitOr for Formula {
     type Output = Self;
 
@@ -381,3 +476,6 @@ impl BitVector {
         form
     }
 }
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,202 @@
+use std::collections::HashMap;
+
+use crate::tests::Rng;
+
+use super::*;
+
+type Assignment = HashMap<i64, bool>;
+
+fn value(a: Atom, assignment: &Assignment) -> bool {
+    assignment[&a.0.abs()] == (a.0 > 0)
+}
+
+/// Evaluates `f` with `a` set to `v`, restoring any outer binding of `a` afterwards.
+fn bind(assignment: &mut Assignment, a: Atom, v: bool, f: impl FnOnce(&mut Assignment) -> bool) -> bool {
+    let outer = assignment.insert(a.0.abs(), v);
+    let ret = f(assignment);
+    match outer {
+        Some(outer) => assignment.insert(a.0.abs(), outer),
+        None => assignment.remove(&a.0.abs()),
+    };
+    ret
+}
+
+/// Brute force over every assignment of the quantified atoms.
+fn eval(formula: &Formula, assignment: &mut Assignment) -> bool {
+    match formula {
+        Formula::Atom(a) => value(*a, assignment),
+        Formula::Not(f) => !eval(f, assignment),
+        Formula::Exists(a, f) => [false, true].into_iter().any(|v| bind(assignment, *a, v, |assignment| eval(f, assignment))),
+        Formula::Forall(a, f) => [false, true].into_iter().all(|v| bind(assignment, *a, v, |assignment| eval(f, assignment))),
+        Formula::And(a, b) => eval(a, assignment) && eval(b, assignment),
+        Formula::Or(a, b) => eval(a, assignment) || eval(b, assignment),
+    }
+}
+
+fn eval_prenex_cnf(quantifiers: &[(Quantifier, Atom)], matrix: &[Vec<Atom>], assignment: &mut Assignment) -> bool {
+    match quantifiers.split_first() {
+        None => matrix.iter().all(|clause| clause.iter().any(|a| value(*a, assignment))),
+        Some(((quantifier, a), rest)) => {
+            let mut branch = |v| bind(assignment, *a, v, |assignment| eval_prenex_cnf(rest, matrix, assignment));
+            match quantifier {
+                Quantifier::Exists => branch(false) || branch(true),
+                Quantifier::Forall => branch(false) && branch(true),
+            }
+        },
+    }
+}
+
+/// Reads back the output of `qdimacs`, checking its header along the way.
+fn parse_qdimacs(src: &str) -> (Vec<(Quantifier, Atom)>, Vec<Vec<Atom>>) {
+    let mut lines = src.lines();
+    let header: Vec<usize> = lines.next().unwrap().strip_prefix("p cnf ").unwrap()
+        .split(' ').map(|n| n.parse().unwrap()).collect();
+    let mut quantifiers = Vec::new();
+    let mut matrix = Vec::new();
+    for line in lines {
+        let (quantifier, rest) = match line.split_once(' ') {
+            Some(("a", rest)) => (Some(Quantifier::Forall), rest),
+            Some(("e", rest)) => (Some(Quantifier::Exists), rest),
+            _ => (None, line),
+        };
+        let atoms: Vec<Atom> = rest.split(' ').filter(|s| !s.is_empty()).map(|n| Atom(n.parse().unwrap())).collect();
+        assert_eq!(atoms.last().unwrap().0, 0, "line not terminated by 0");
+        let atoms = atoms[..atoms.len() - 1].iter().copied();
+        match quantifier {
+            Some(Quantifier::Forall) => quantifiers.extend(atoms.map(|a| (Quantifier::Forall, a))),
+            Some(Quantifier::Exists) => quantifiers.extend(atoms.map(|a| (Quantifier::Exists, a))),
+            None => matrix.push(atoms.collect()),
+        }
+    }
+    assert_eq!(header[1], matrix.len(), "clause count");
+    let max = quantifiers.iter().map(|(_, a)| a.0.abs()).chain(matrix.iter().flatten().map(|a: &Atom| a.0.abs())).max().unwrap_or(0);
+    assert!(max as usize <= header[0], "atom beyond declared count");
+    (quantifiers, matrix)
+}
+
+/// A closed formula over a small pool of atoms, so atoms are regularly quantified more than
+/// once, both nested and side by side.
+fn random_formula(rng: &mut Rng, pool: &[Atom], bound: &mut Vec<Atom>, budget: &mut i64) -> Formula {
+    let quantify = bound.is_empty() || (*budget > 0 && rng.percent(30));
+    if quantify {
+        let a = rng.pick(pool);
+        bound.push(a);
+        let f = random_formula(rng, pool, bound, budget);
+        bound.pop();
+        return if rng.percent(50) { a.exists(f) } else { a.forall(f) };
+    }
+    if *budget <= 0 || rng.percent(25) {
+        let a = rng.pick(bound);
+        return if rng.percent(50) { !!a } else { !a };
+    }
+    *budget -= 1;
+    match rng.range(0, 2) {
+        0 => random_formula(rng, pool, bound, budget) & random_formula(rng, pool, bound, budget),
+        1 => random_formula(rng, pool, bound, budget) | random_formula(rng, pool, bound, budget),
+        _ => !random_formula(rng, pool, bound, budget),
+    }
+}
+
+fn negation_free(formula: &Formula) -> bool {
+    match formula {
+        Formula::Atom(_) => true,
+        Formula::Not(_) => false,
+        Formula::Exists(_, f) | Formula::Forall(_, f) => negation_free(f),
+        Formula::And(a, b) | Formula::Or(a, b) => negation_free(a) && negation_free(b),
+    }
+}
+
+fn quantifier_free(formula: &Formula) -> bool {
+    match formula {
+        Formula::Atom(_) => true,
+        Formula::Not(f) => quantifier_free(f),
+        Formula::Exists(..) | Formula::Forall(..) => false,
+        Formula::And(a, b) | Formula::Or(a, b) => quantifier_free(a) && quantifier_free(b),
+    }
+}
+
+fn prenex(formula: &Formula) -> bool {
+    match formula {
+        Formula::Exists(_, f) | Formula::Forall(_, f) => prenex(f),
+        f => quantifier_free(f),
+    }
+}
+
+/// Checks every transformation on the way to QDIMACS against the truth value of `formula`.
+fn check_transformations(formula: Formula) {
+    let expected = eval(&formula, &mut Assignment::new());
+    assert_eq!(formula.evaluate(), expected, "evaluate disagrees on {formula}");
+
+    let denegified = formula.clone().denegify();
+    assert!(negation_free(&denegified), "denegify left a negation in {denegified}");
+    assert_eq!(eval(&denegified, &mut Assignment::new()), expected, "denegify changed {formula}");
+
+    let prenexed = denegified.prenexify();
+    assert!(prenex(&prenexed), "prenexify left an inner quantifier in {prenexed}");
+    assert_eq!(eval(&prenexed, &mut Assignment::new()), expected, "prenexify changed {formula}");
+
+    let (quantifiers, matrix) = prenexed.prenex_to_prenex_cnf();
+    assert_eq!(eval_prenex_cnf(&quantifiers, &matrix, &mut Assignment::new()), expected, "tseitin changed {formula}");
+
+    let (quantifiers, matrix) = parse_qdimacs(&qdimacs(&quantifiers, &matrix));
+    assert_eq!(eval_prenex_cnf(&quantifiers, &matrix, &mut Assignment::new()), expected, "qdimacs changed {formula}");
+}
+
+#[test]
+fn transformations_preserve_truth() {
+    for seed in 0..500 {
+        let mut rng = Rng(seed);
+        let pool: Vec<Atom> = (0..rng.range(1, 4)).map(|_| atom()).collect();
+        let mut budget = rng.range(0, 7);
+        let formula = random_formula(&mut rng, &pool, &mut Vec::new(), &mut budget);
+        check_transformations(formula);
+    }
+}
+
+#[test]
+fn nested_quantifier_does_not_capture() {
+    let a = atom();
+    // ∃a(∀a(a) ∨ a) is true, ∃a∀a(a ∨ a) is not.
+    check_transformations(a.exists(a.forall(!!a) | !!a));
+    check_transformations(!a.exists(!a.forall(!a) & !!a));
+}
+
+#[test]
+fn sibling_quantifiers_do_not_capture() {
+    let a = atom();
+    // (∃a a) ∧ (∃a ¬a) is true, ∃a∃a(a ∧ ¬a) is not.
+    check_transformations(a.exists(!!a) & a.exists(!a));
+    check_transformations(a.forall(!!a | !a) & a.exists(!a) | a.forall(!!a));
+}
+
+#[test]
+fn evaluate_memoises_irrelevant_atoms() {
+    // 2^40 assignments without memoisation.
+    let atoms: Vec<Atom> = (0..40).map(|_| atom()).collect();
+    let inner = atoms[0].forall(!!atoms[0] | !atoms[0]);
+    let formula = atoms[1..].iter().fold(inner, |f, a| if a.0 % 2 == 0 { a.exists(f) } else { a.forall(f) });
+    assert!(formula.evaluate());
+}
+
+#[test]
+fn eval_under_negated_key() {
+    let a = atom();
+    assert!((!a).eval_under(&HashMap::from([(a.invert(), true)])));
+    assert!(!(!!a).eval_under(&HashMap::from([(a.invert(), true)])));
+}
+
+#[test]
+fn bitvector_comparisons() {
+    let bits = BitVector::new(3);
+    for val in 0..8 {
+        let le = bits.le(val);
+        let ge = bits.ge(val);
+        let equal = bits.equal(val);
+        for n in 0..8u64 {
+            let assignment: HashMap<Atom, bool> = bits.bits.iter().enumerate().map(|(i, a)| (*a, n >> i & 1 == 1)).collect();
+            assert_eq!(le.eval_under(&assignment), n <= val);
+            assert_eq!(ge.eval_under(&assignment), n >= val);
+            assert_eq!(equal.eval_under(&assignment), n == val);
+        }
+    }
+}
@@ -0,0 +1,155 @@
+//! Game records and the moves they hold.
+
+use std::{fmt::{self, Display, Formatter}, str::FromStr};
+
+use crate::{bddl::{Domain, Player, Problem, Stuck}, solver::{Board, PASS}};
+
+/// A game as the lines `problem <path>` and `domain <path>`, relative to the record, followed by
+/// moves like `black place 1 1` or `white pass`. `#` starts a comment.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Record {
+    pub problem: String,
+    pub domain: String,
+    pub moves: Vec<Move>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Move {
+    pub player: Player,
+    /// The action, or `PASS`.
+    pub action: String,
+    pub x: i64,
+    pub y: i64,
+    pub args: Vec<i64>,
+}
+
+impl Move {
+    /// A move given as `pass`, or as an action followed by its anchor and extra parameters.
+    fn new(player: Player, words: &[&str]) -> Option<Move> {
+        let (action, numbers) = words.split_first()?;
+        let numbers = numbers.iter().map(|n| n.parse()).collect::<Result<Vec<i64>, _>>().ok()?;
+        match numbers[..] {
+            [] if *action == PASS => Some(Move { player, action: PASS.to_owned(), x: 0, y: 0, args: Vec::new() }),
+            [x, y, ..] => Some(Move { player, action: (*action).to_owned(), x, y, args: numbers[2..].to_vec() }),
+            _ => None,
+        }
+    }
+}
+
+/// Moves separated by commas, each written like in a record but without its player, which the
+/// turn order of the problem gives.
+pub fn parse_moves(problem: &Problem, moves: &str) -> Result<Vec<Move>, String> {
+    moves.split(',')
+        .filter(|m| !m.trim().is_empty())
+        .enumerate()
+        .map(|(ply, m)| {
+            let words: Vec<&str> = m.split_whitespace().collect();
+            Move::new(problem.turns.mover(ply as u64), &words).ok_or_else(|| format!("cannot read move {}", m.trim()))
+        })
+        .collect()
+}
+
+impl FromStr for Record {
+    type Err = String;
+
+    /// Fails with the line of the first malformed entry.
+    fn from_str(src: &str) -> Result<Self, Self::Err> {
+        let (mut problem, mut domain, mut moves) = (None, None, Vec::new());
+        for (i, line) in src.lines().enumerate() {
+            let words: Vec<&str> = line.split('#').next().unwrap().split_whitespace().collect();
+            let error = || format!("line {}: cannot read {}", i + 1, line.trim());
+            match words[..] {
+                [] => {},
+                ["problem", path] if problem.is_none() => problem = Some(path.to_owned()),
+                ["domain", path] if domain.is_none() => domain = Some(path.to_owned()),
+                [player, ref rest @ ..] => moves.push(player.parse().ok().and_then(|player| Move::new(player, rest)).ok_or_else(error)?),
+            }
+        }
+        match (problem, domain) {
+            (Some(problem), Some(domain)) => Ok(Record { problem, domain, moves }),
+            _ => Err("a record names a problem and a domain".to_owned()),
+        }
+    }
+}
+
+/// Plays the moves from the initial position of the problem, checking that each is made by the
+/// player to move and is accepted by `Board::play`. Returns a line describing each move with
+/// the goals it fired, and the board after it. Fails with the number of the first move that is
+/// wrong or comes after the end of the game.
+pub fn plies<'a>(problem: &'a Problem, domain: &'a Domain, moves: &[Move]) -> Result<Vec<(String, Board<'a>)>, String> {
+    let mut board = Board::initial(problem, domain);
+    let mut plies = Vec::new();
+    let mut over = false;
+    for (ply, m) in moves.iter().enumerate() {
+        let error = |e: &str| format!("move {}: {}", ply + 1, e);
+        let player = problem.turns.mover(ply as u64);
+        let actions = domain.actions(player);
+        if over || ply as u64 == problem.depth || board.stuck(actions) && problem.stuck != Stuck::Pass {
+            return Err(error("the game is over"));
+        }
+        if m.player != player {
+            return Err(error(&format!("{player} is to move")));
+        }
+        board.play(actions, &m.action, m.x, m.y, &m.args).map_err(|e| error(&e))?;
+        let mut line = format!("{}. {m}", ply + 1);
+        let fired: Vec<String> = problem.goals(player).iter()
+            .filter(|goal| board.assert_goals(std::slice::from_ref(goal)))
+            .map(|goal| goal.to_string())
+            .collect();
+        if !fired.is_empty() {
+            line.push_str(&format!(", {player} wins with {}", fired.join(" and ")));
+            over = true;
+        }
+        plies.push((line, board.clone()));
+    }
+    Ok(plies)
+}
+
+/// The lines of `plies`, followed by a last one if the player to move then cannot move and
+/// the game ends with that, and the board reached.
+pub fn replay<'a>(problem: &'a Problem, domain: &'a Domain, moves: &[Move]) -> Result<(Vec<String>, Board<'a>), String> {
+    let plies = plies(problem, domain, moves)?;
+    let board = plies.last().map_or_else(|| Board::initial(problem, domain), |(_, board)| board.clone());
+    let mut report: Vec<String> = plies.into_iter().map(|(line, _)| line).collect();
+    let over = moves.last().is_some_and(|m| board.assert_goals(problem.goals(m.player)));
+    let ply = moves.len() as u64;
+    let player = problem.turns.mover(ply);
+    if !over && ply < problem.depth && board.stuck(domain.actions(player)) {
+        match problem.stuck {
+            Stuck::Loss => report.push(format!("{player} cannot move and loses")),
+            Stuck::Draw => report.push(format!("{player} cannot move, the game is drawn")),
+            Stuck::Pass => {},
+        }
+    }
+    Ok((report, board))
+}
+
+/// The problem starting from the position the moves reach, see `replay`. Fails as well if the
+/// last move ends the game.
+pub fn position(problem: &Problem, domain: &Domain, moves: &[Move]) -> Result<Problem, String> {
+    let (_, board) = replay(problem, domain, moves)?;
+    if let Some(last) = moves.last() {
+        if board.assert_goals(problem.goals(last.player)) {
+            return Err(format!("move {}: the game is over", moves.len()));
+        }
+    }
+    Ok(board.problem(moves.len() as u64))
+}
+
+impl Display for Move {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        if self.action == PASS {
+            return write!(f, "{} {}", self.player, PASS);
+        }
+        write!(f, "{} {} {} {}", self.player, self.action, self.x, self.y)?;
+        self.args.iter().try_for_each(|arg| write!(f, " {arg}"))
+    }
+}
+
+impl Display for Record {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        writeln!(f, "problem {}", self.problem)?;
+        writeln!(f, "domain {}", self.domain)?;
+        self.moves.iter().try_for_each(|m| writeln!(f, "{m}"))
+    }
+}
@@ -0,0 +1,86 @@
+//! Pictures of positions and lines of play.
+
+use std::fmt::Write;
+
+use crate::{bddl::{Domain, Pred, Problem}, record::{self, Move}, solver::Board};
+
+/// Side of a cell in SVG units.
+const CELL: i64 = 40;
+/// Room for the coordinates left of and above the board.
+const MARGIN: i64 = 30;
+/// Height of a line of text below the board.
+const TEXT: i64 = 20;
+
+/// The positions of a line of play, starting with the initial one. Each comes with a caption
+/// naming the move leading to it and the goals it fired, see `record::plies`.
+pub fn line<'a>(problem: &'a Problem, domain: &'a Domain, moves: &[Move]) -> Result<Vec<(String, Board<'a>)>, String> {
+    let mut positions = vec![("initial position".to_owned(), Board::initial(problem, domain))];
+    positions.extend(record::plies(problem, domain, moves)?);
+    Ok(positions)
+}
+
+/// The positions one below the other, each under its caption.
+pub fn pages(positions: &[(String, Board)]) -> String {
+    positions.iter().map(|(caption, board)| format!("{caption}\n{board}")).collect::<Vec<_>>().join("\n")
+}
+
+/// A picture of a single position.
+pub fn svg(problem: &Problem, domain: &Domain, board: &Board) -> String {
+    let (width, height) = extent(problem, domain, 0);
+    format!("{}{}</svg>\n", header(width, height), elements(problem, domain, board))
+}
+
+/// The positions in turn, each shown for `seconds` before the last one stays.
+pub fn animation(problem: &Problem, domain: &Domain, positions: &[(String, Board)], seconds: u64) -> String {
+    let (width, height) = extent(problem, domain, 1);
+    let mut svg = header(width, height);
+    for (i, (caption, board)) in positions.iter().enumerate() {
+        let end = if i + 1 == positions.len() { "indefinite".to_owned() } else { format!("{}s", seconds) };
+        writeln!(svg, "<g visibility=\"hidden\"><set attributeName=\"visibility\" to=\"visible\" begin=\"{}s\" dur=\"{}\"/>", i as u64 * seconds, end).unwrap();
+        svg.push_str(&elements(problem, domain, board));
+        writeln!(svg, "<text x=\"{}\" y=\"{}\">{}</text>", MARGIN, below(problem, domain.variables.len()), caption).unwrap();
+        svg.push_str("</g>\n");
+    }
+    svg + "</svg>\n"
+}
+
+/// Width and height of a picture with `captions` lines of text besides the variables.
+fn extent(problem: &Problem, domain: &Domain, captions: usize) -> (i64, i64) {
+    (2 * MARGIN + problem.size.x * CELL, below(problem, domain.variables.len() + captions) + MARGIN / 2)
+}
+
+/// The baseline of the line of text `i` below the board.
+fn below(problem: &Problem, i: usize) -> i64 {
+    MARGIN + problem.size.y * CELL + (i as i64 + 1) * TEXT
+}
+
+fn header(width: i64, height: i64) -> String {
+    format!("<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{width}\" height=\"{height}\" font-family=\"sans-serif\" font-size=\"14\">\n")
+}
+
+/// The cells with their coordinates, and the variables below them.
+fn elements(problem: &Problem, domain: &Domain, board: &Board) -> String {
+    let mut svg = String::new();
+    let center = |i: i64| MARGIN + i * CELL + CELL / 2;
+    for x in 0..problem.size.x {
+        let label = problem.positions.get(x as usize).cloned().unwrap_or_else(|| x.to_string());
+        writeln!(svg, "<text x=\"{}\" y=\"{}\" text-anchor=\"middle\">{}</text>", center(x), MARGIN - 8, label).unwrap();
+    }
+    for y in 0..problem.size.y {
+        writeln!(svg, "<text x=\"{}\" y=\"{}\" text-anchor=\"end\" dominant-baseline=\"central\">{}</text>", MARGIN - 8, center(y), y).unwrap();
+    }
+    for (x, y) in problem.cells() {
+        let (cx, cy) = (center(x), center(y));
+        writeln!(svg, "<rect x=\"{}\" y=\"{}\" width=\"{CELL}\" height=\"{CELL}\" fill=\"#e3c16f\" stroke=\"black\"/>", cx - CELL / 2, cy - CELL / 2).unwrap();
+        match board.pred((x, y)) {
+            Pred::Open => {},
+            Pred::Black => writeln!(svg, "<circle cx=\"{cx}\" cy=\"{cy}\" r=\"{}\" fill=\"black\"/>", CELL * 2 / 5).unwrap(),
+            Pred::White => writeln!(svg, "<circle cx=\"{cx}\" cy=\"{cy}\" r=\"{}\" fill=\"white\" stroke=\"black\"/>", CELL * 2 / 5).unwrap(),
+            Pred::Declared(name) => writeln!(svg, "<text x=\"{cx}\" y=\"{cy}\" text-anchor=\"middle\" dominant-baseline=\"central\" font-size=\"11\">{name}</text>").unwrap(),
+        }
+    }
+    for (i, (variable, value)) in domain.variables.iter().zip(board.values()).enumerate() {
+        writeln!(svg, "<text x=\"{}\" y=\"{}\">{} = {}</text>", MARGIN, below(problem, i), variable.name, value).unwrap();
+    }
+    svg
+}
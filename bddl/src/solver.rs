@@ -1,83 +1,216 @@
-use std::iter::repeat;
+use std::{fmt::{self, Display, Formatter}, iter::repeat};
 
 use super::bddl::*;
 
+/// A position of a game, played on by the explicit solver and by `play`.
 #[derive(Debug, Clone)]
-struct Board {
+pub struct Board<'a> {
     preds: Vec<Vec<Pred>>,
+    /// The variables, numbered like in `Domain::variables`.
+    values: Vec<i64>,
     size: Size,
+    problem: &'a Problem,
+    domain: &'a Domain,
 }
 
-impl Board {
-    fn new(size: Size) -> Board {
-        Board { preds: vec![vec![Pred::Open; size.y as _]; size.x as _], size }
+impl<'a> Board<'a> {
+    fn new(problem: &'a Problem, domain: &'a Domain) -> Board<'a> {
+        let size = problem.size;
+        Board { preds: vec![vec![Pred::Open; size.y as _]; size.x as _], values: domain.initial_values(problem), size, problem, domain }
     }
 
-    fn assert_pred(&self, x: i64, y: i64, pred: Pred) -> bool {
-        0 <= x && x < self.size.x && 0 <= y && y < self.size.y && self.preds[x as usize][y as usize] == pred
+    /// The board of the problem before the first move.
+    pub fn initial(problem: &'a Problem, domain: &'a Domain) -> Board<'a> {
+        let mut board = Board::new(problem, domain);
+        for init_pred in &problem.init {
+            board.effect(init_pred.x, init_pred.y, init_pred.pred.clone());
+        }
+        board
     }
 
-    fn assert_not_pred(&self, x: i64, y: i64, pred: Pred) -> bool {
-        0 <= x && x < self.size.x && 0 <= y && y < self.size.y && self.preds[x as usize][y as usize] != pred
+    /// The problem starting from this board as the move `ply`, with the depth left after it.
+    /// Panics if `ply` exceeds the depth.
+    pub fn problem(&self, ply: u64) -> Problem {
+        let init = self.problem.cells()
+            .filter(|&(x, y)| self.preds[x as usize][y as usize] != Pred::Open)
+            .map(|(x, y)| InitPred { pred: self.preds[x as usize][y as usize].clone(), x, y })
+            .collect();
+        let values = self.domain.variables.iter().zip(&self.values)
+            .filter(|(_, value)| **value != 0)
+            .map(|(variable, value)| (variable.name.clone(), *value))
+            .collect();
+        self.problem.with_board(init, values, ply).unwrap_or_else(|e| panic!("{}", e))
     }
 
-    fn assert_condition(&self, condition: &Condition, x: i64, y: i64) -> bool {
-        condition.sub_cond.iter().all(|cond| {
-            match cond {
-                SubCondition::Id { pred, x_e, y_e } => self.assert_pred(x_e.noramlize_t(x, self.size.x), y_e.noramlize_t(y, self.size.y), *pred),
-                SubCondition::Not { pred, x_e, y_e } => self.assert_not_pred(x_e.noramlize_t(x, self.size.x), y_e.noramlize_t(y, self.size.y), *pred),
-            }
-        })
+    fn assert_pred(&self, cell: Option<(i64, i64)>, pred: &Pred) -> bool {
+        cell.is_some_and(|(x, y)| self.preds[x as usize][y as usize] == *pred)
+    }
+
+    fn assert_not_pred(&self, cell: Option<(i64, i64)>, pred: &Pred) -> bool {
+        cell.is_some_and(|(x, y)| self.preds[x as usize][y as usize] != *pred)
+    }
+
+    fn assert_condition(&self, condition: &Condition, x: i64, y: i64, args: &[i64]) -> bool {
+        match condition {
+            Condition::Atom(SubCondition::Id { pred, x_e, y_e, via }) => self.assert_pred(self.problem.target(x_e, y_e, via, x, y, args), pred),
+            Condition::Atom(SubCondition::Not { pred, x_e, y_e, via }) => self.assert_not_pred(self.problem.target(x_e, y_e, via, x, y, args), pred),
+            Condition::Value { variable, value } => self.values[self.domain.variable(variable)] == *value,
+            Condition::And(conditions) => conditions.iter().all(|c| self.assert_condition(c, x, y, args)),
+            Condition::Or(conditions) => conditions.iter().any(|c| self.assert_condition(c, x, y, args)),
+            Condition::Not(condition) => !self.assert_condition(condition, x, y, args),
+        }
     }
 
-    fn effect_conditon(&mut self, condition: &Condition, x: i64, y: i64) {
-        condition.sub_cond.iter().for_each(|cond| {
+    /// Plays the action. Effects on cells off the board are dropped, like in the symbolic
+    /// encodings.
+    pub fn effect_conditon(&mut self, action: &Action, x: i64, y: i64, args: &[i64]) {
+        let before = self.preds.clone();
+        let values = self.values.clone();
+        for assignment in &action.assignments {
+            let index = self.domain.variable(assignment.variable());
+            self.values[index] = assignment.apply(values[index], self.domain.variables[index].max);
+        }
+        action.effect.iter().for_each(|cond| {
             match cond {
-                SubCondition::Id { pred, x_e, y_e } => self.effect(x_e.noramlize_t(x, self.size.x), y_e.noramlize_t(y, self.size.y), *pred),
-                SubCondition::Not { .. } => panic!("Cannot use not subcondition in effect"), 
+                SubCondition::Id { pred, x_e, y_e, via } => if let Some((x, y)) = self.problem.target(x_e, y_e, via, x, y, args) {
+                    self.effect(x, y, pred.clone());
+                },
+                SubCondition::Not { pred, x_e, y_e, via } => if let Some((x, y)) = self.problem.target(x_e, y_e, via, x, y, args) {
+                    if before[x as usize][y as usize] == *pred {
+                        self.effect(x, y, Pred::Open);
+                    }
+                },
             };
         })
         
     }
 
-    fn actions(&self, precondition: &Condition) -> Vec<(i64, i64)> {
+    /// The anchors and extra parameter values the action is applicable with.
+    pub fn actions(&self, action: &Action) -> Vec<(i64, i64, Vec<i64>)> {
+        let assignments = self.size.assignments(action.parameters.len());
         (0..self.size.x).flat_map(|x| repeat(x).zip(0..self.size.y))
-            .filter(|&(x, y)| self.assert_condition(precondition, x, y))
+            .flat_map(|(x, y)| assignments.iter().map(move |args| (x, y, args.clone())))
+            .filter(|(x, y, args)| self.assert_condition(&action.precondition, *x, *y, args))
             .collect()
     }
 
+    /// Plays the move of one of `actions` named `name`, anchored at `(x, y)` with the extra
+    /// parameter values `args`. A `PASS` is only applicable when none of the actions is and
+    /// the problem passes then.
+    pub fn play(&mut self, actions: &[Action], name: &str, x: i64, y: i64, args: &[i64]) -> Result<(), String> {
+        if name == PASS {
+            if self.problem.stuck == Stuck::Pass && self.stuck(actions) {
+                return Ok(());
+            }
+            return Err(format!("{PASS} is not an applicable move"));
+        }
+        let found = actions.iter().find(|action| action.name == name && self.actions(action).contains(&(x, y, args.to_vec())));
+        match found {
+            Some(action) => {
+                self.effect_conditon(action, x, y, args);
+                Ok(())
+            },
+            None => {
+                let args: String = args.iter().map(|arg| format!(" {arg}")).collect();
+                Err(format!("{name} {x} {y}{args} is not an applicable move"))
+            },
+        }
+    }
+
+    pub fn pred(&self, (x, y): Cell) -> &Pred {
+        &self.preds[x as usize][y as usize]
+    }
+
+    pub fn values(&self) -> &[i64] {
+        &self.values
+    }
+
     fn effect(&mut self, x: i64, y: i64, pred: Pred) {
         self.preds[x as usize][y as usize] = pred;
     }
 
-    fn assert_goals(&self, goal: &[Condition]) -> bool {
+    /// Whether none of the actions is applicable.
+    pub fn stuck(&self, actions: &[Action]) -> bool {
+        actions.iter().all(|action| self.actions(action).is_empty())
+    }
+
+    pub fn assert_goals(&self, goal: &[Condition]) -> bool {
         (0..self.size.x).flat_map(|x| repeat(x).zip(0..self.size.y))
-            .any(|(x, y)| goal.iter().any(|c| self.assert_condition(c, x, y)))
+            .any(|(x, y)| goal.iter().any(|c| self.assert_condition(c, x, y, &[])))
     }
 }
 
-pub fn solve(problem: &Problem, domain: &Domain) -> Option<(String, i64, i64)> {
-    let mut board = Board::new(problem.size);
-    for init_pred in &problem.init {
-        board.effect(init_pred.x, init_pred.y, init_pred.pred);
+/// A row of cells for each `y` below the values of `x`, `.` for open cells, `X` for black,
+/// `O` for white, and the declared states numbered from 1. The values of the variables follow.
+impl Display for Board<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let width = (self.size.x - 1).max(1).to_string().len();
+        let margin = (self.size.y - 1).max(1).to_string().len();
+        let header = (0..self.size.x).map(|x| format!("{x:>width$}")).collect::<Vec<_>>();
+        writeln!(f, "{:margin$} {}", "", header.join(" "))?;
+        for y in 0..self.size.y {
+            let row = (0..self.size.x)
+                .map(|x| match &self.preds[x as usize][y as usize] {
+                    Pred::Open => format!("{:>width$}", "."),
+                    Pred::Black => format!("{:>width$}", "X"),
+                    Pred::White => format!("{:>width$}", "O"),
+                    pred => format!("{:>width$}", self.domain.state(pred) - 2),
+                })
+                .collect::<Vec<_>>();
+            writeln!(f, "{y:>margin$} {}", row.join(" "))?;
+        }
+        for (i, name) in self.domain.predicates.iter().enumerate() {
+            writeln!(f, "{}: {}", i + 1, name)?;
+        }
+        for (variable, value) in self.domain.variables.iter().zip(&self.values) {
+            writeln!(f, "{} = {}", variable.name, value)?;
+        }
+        Ok(())
     }
-    solve_black(problem, domain, board, problem.depth)
 }
 
-fn solve_black(problem: &Problem, domain: &Domain, board: Board, depth: u64) -> Option<(String, i64, i64)> {
+/// Action name of the move returned when black has no applicable action and passes. A pass
+/// leaves the board unchanged, goals are checked after it like after any other move.
+pub const PASS: &str = "pass";
+
+/// An action with the anchor and extra parameter values it is applied with.
+pub type FirstMove = (String, i64, i64, Vec<i64>);
+
+/// Whether black has a forced win, with its first move if black moves first.
+pub fn solve(problem: &Problem, domain: &Domain) -> Option<Option<FirstMove>> {
+    let board = Board::initial(problem, domain);
+    match problem.turns.mover(0) {
+        Player::Black => solve_black(problem, domain, board, problem.depth).map(Some),
+        Player::White => solve_white(problem, domain, board, problem.depth).then_some(None),
+    }
+}
+
+/// Whether black wins with `depth` moves left, whoever the turn order gives the next one to.
+fn solve_next(problem: &Problem, domain: &Domain, board: Board, depth: u64) -> bool {
+    match problem.turns.mover(problem.depth - depth) {
+        Player::Black => solve_black(problem, domain, board, depth).is_some(),
+        Player::White => solve_white(problem, domain, board, depth),
+    }
+}
+
+fn solve_black(problem: &Problem, domain: &Domain, board: Board, depth: u64) -> Option<FirstMove> {
     if depth == 0 {
         return None;
     }
+    if board.stuck(&domain.black_actions) {
+        return match problem.stuck {
+            Stuck::Pass => (board.assert_goals(&problem.black_goals) || solve_next(problem, domain, board, depth - 1))
+                .then(|| (PASS.to_owned(), 0, 0, Vec::new())),
+            Stuck::Loss | Stuck::Draw => None,
+        };
+    }
     for action in &domain.black_actions {
-        let valids = board.actions(&action.precondition);
-        for (x, y) in valids {
+        let valids = board.actions(action);
+        for (x, y, args) in valids {
             let mut board = board.clone();
-            board.effect_conditon(&action.effect, x, y);
-            if board.assert_goals(&problem.black_goals) {
-                return Some((action.name.clone(), x, y));
-            }
-            if solve_white(problem, domain, board, depth - 1) {
-                return Some((action.name.clone(), x, y));
+            board.effect_conditon(action, x, y, &args);
+            if board.assert_goals(&problem.black_goals) || solve_next(problem, domain, board, depth - 1) {
+                return Some((action.name.clone(), x, y, args));
             }
         }
     }
@@ -88,16 +221,22 @@ fn solve_white(problem: &Problem, domain: &Domain, board: Board, depth: u64) ->
     if depth == 0 {
         return false;
     }
+    if board.stuck(&domain.white_actions) {
+        return match problem.stuck {
+            Stuck::Loss => true,
+            Stuck::Draw => false,
+            Stuck::Pass => !board.assert_goals(&problem.white_goals) && solve_next(problem, domain, board, depth - 1),
+        };
+    }
     for action in &domain.white_actions {
-        let valids = board.actions(&action.precondition);
-        for (x, y) in valids {
+        let valids = board.actions(action);
+        for (x, y, args) in valids {
             let mut board = board.clone();
-            board.effect_conditon(&action.effect, x, y);
+            board.effect_conditon(action, x, y, &args);
             if board.assert_goals(&problem.white_goals) {
                 return false;
             }
-            let f = solve_black(problem, domain, board.clone(), depth - 1);
-            if f.is_none() {
+            if !solve_next(problem, domain, board, depth - 1) {
                 return false;
             }
         }
@@ -1,6 +1,37 @@
-use std::{iter::repeat, convert::identity};
+use std::{iter::repeat, str::FromStr};
 
-use crate::{qbf::{BitVector, Formula, Atom, atom}, bddl::{E, Size, Pred, InitPred, SubCondition, Condition, Action, Domain, Problem}};
+use crate::{goals::{self, Line, Literal}, qbf::{BitVector, Formula, Atom, atom}, bddl::{E, Size, Pred, InitPred, SubCondition, Condition, Action, Domain, Problem, Stuck, Cell, Variable, Player}, position::{Position, PositionEncoding}};
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Options {
+    /// Check goals on their precompiled lines instead of quantifying over an anchor.
+    pub flatten: bool,
+    pub position: PositionEncoding,
+    pub transition: Transition,
+}
+
+/// How a board is tied to the board before the move.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Transition {
+    /// Every cell is a nested if-then-else over all effects, the last firing effect wins.
+    #[default]
+    Chained,
+    /// Explanatory frame axioms: the last effect targeting a cell forces its state, and a cell
+    /// keeps its state unless some effect targets it.
+    Frame,
+}
+
+impl FromStr for Transition {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "chained" => Ok(Transition::Chained),
+            "frame" => Ok(Transition::Frame),
+            _ => Err(()),
+        }
+    }
+}
 
 struct Context {
     truth: Atom,
@@ -8,243 +39,406 @@ struct Context {
     board: SymbolicBoard,
     domain: Domain,
     problem: Problem,
-    flatten: bool,
+    options: Options,
+    black_lines: Vec<Line>,
+    white_lines: Vec<Line>,
 }
 
-pub fn solve(problem: Problem, domain: Domain, flatten: bool) -> Formula {
-    let fake_board = SymbolicBoard { size: problem.size, symbols: Vec::new() };
+pub fn solve(problem: Problem, domain: Domain, options: Options) -> Formula {
+    let fake_board = SymbolicBoard { size: problem.size, symbols: Vec::new(), values: Vec::new() };
     let mut context = Context {
         truth: atom(),
         size: problem.size,
         board: fake_board,
         domain,
+        black_lines: goals::compile(&problem.black_goals, &problem),
+        white_lines: goals::compile(&problem.white_goals, &problem),
         problem,
-        flatten,
+        options,
     };
     context.board = SymbolicBoard::init(&context, &context.problem.init, context.problem.size);
-    !!context.truth & context.solve_black(context.problem.depth)
+    let status = Status { won: context.truth.invert(), lost: context.truth.invert() };
+    let truth = context.truth;
+    truth.exists(!!truth & context.solve_next(context.problem.depth, status))
+}
+
+/// Whether black or white has reached a goal. Once either has, the game is over and the
+/// remaining plies are unconstrained.
+#[derive(Clone, Copy)]
+struct Status {
+    won: Atom,
+    lost: Atom,
+}
+
+impl Status {
+    fn over(&self) -> Formula {
+        !!self.won | !!self.lost
+    }
 }
 
 impl Context {
-    fn gen_bounds_check(&self, e: &E, v: &BitVector, max: u64) -> Formula {
-        v.ge(0) & v.le(max - 1) & match e {
-            &E::Add(i) => v.le(max - i as u64 - 1),
-            &E::Sub(i) => v.ge(i as u64) & v.le(max - 1),
-            _ => !!self.truth,
-        } 
-    }
-
-    fn gen_e_bv_eq(&self, e: &E, v: &BitVector, target: u64, max: u64) -> Formula {
-        match e {
-            &E::Add(i) => if i as u64 > target { !self.truth } else { v.equal(target - i as u64) },
-            &E::Sub(i) => if i as u64 + target >= max { !self.truth } else { v.equal(target + i as u64) },
-            &E::Int(i) => if i as u64 == target { !!self.truth } else { !self.truth },
-            &E::Identity => v.equal(target),
-            &E::Min => if 0 == target { !!self.truth } else { !self.truth },
-            &E::Max => if max == target + 1 { !!self.truth } else { !self.truth },
-        }
+    fn position(&self, parameters: usize) -> Position {
+        Position::new(self.options.position, self.size, parameters, self.truth)
     }
-    
-    fn pred_to_atoms(&self, pred: Pred) -> (Atom, Atom) {
-        match pred {
-            Pred::Open => (self.truth, self.truth.invert()),
-            Pred::White => (self.truth.invert(), self.truth.invert()),
-            Pred::Black => (self.truth.invert(), self.truth),
-        }
+
+    /// Atoms needed for the number of a state, at least one.
+    fn width(&self) -> usize {
+        (2 * self.domain.states().len() - 1).ilog2().max(1) as usize
     }
 
-    fn gen_subcondition(&self, sub_condition: SubCondition, x: &BitVector, y: &BitVector) -> Formula {
-        match sub_condition {
-            SubCondition::Id { pred, x_e, y_e } => {
-                let x_bound = self.gen_bounds_check(&x_e, x, self.size.x as u64);
-                let y_bound = self.gen_bounds_check(&y_e, y, self.size.y as u64);
-                let pred_assert = self.board.gen_pred(self, x, &x_e, y, &y_e, pred);
-                x_bound & y_bound & pred_assert
-            },
-            SubCondition::Not { pred, x_e, y_e } => {
-                let x_bound = self.gen_bounds_check(&x_e, x, self.size.x as u64);
-                let y_bound = self.gen_bounds_check(&y_e, y, self.size.y as u64);
-                let pred_assert = self.board.gen_pred(self, x, &x_e, y, &y_e, pred);
-                x_bound & y_bound & !pred_assert
-            },
+    /// The number `Domain::state` gives the predicate, least significant bit first.
+    fn pred_to_atoms(&self, pred: &Pred) -> Vec<Atom> {
+        self.constant(self.domain.state(pred) as i64, self.width())
+    }
 
-        }
+    fn constant(&self, value: i64, width: usize) -> Vec<Atom> {
+        (0..width)
+            .map(|i| if value >> i & 1 == 1 { self.truth } else { self.truth.invert() })
+            .collect()
+    }
+
+    /// A binary number wide enough for the values of the variable.
+    fn value_width(&self, variable: &Variable) -> usize {
+        (2 * variable.max + 1).ilog2().max(1) as usize
     }
 
-    fn gen_static_subcondition(&self, sub_condition: SubCondition, x: i64, y: i64) -> Option<Formula> {
+    fn gen_subcondition(&self, sub_condition: &SubCondition, position: &Position) -> Formula {
         match sub_condition {
-            SubCondition::Id { pred, x_e, y_e } => {
-                let x = x_e.noramlize(x, self.size.x)?;
-                let y = y_e.noramlize(y, self.size.y)?;
-                Some(self.board.gen_static_pred(self, x as usize, y as usize, pred))
+            SubCondition::Id { pred, x_e, y_e, via } if via.is_empty() => {
+                let bound = position.in_bounds(x_e, y_e);
+                let pred_assert = self.board.gen_pred(self, position, x_e, y_e, pred);
+                bound & pred_assert
             },
-            SubCondition::Not { pred, x_e, y_e } => {
-                let x = x_e.noramlize(x, self.size.x)?;
-                let y = y_e.noramlize(y, self.size.y)?;
-                Some(!self.board.gen_static_pred(self, x as usize, y as usize, pred))
+            SubCondition::Not { pred, x_e, y_e, via } if via.is_empty() => {
+                let bound = position.in_bounds(x_e, y_e);
+                let pred_assert = self.board.gen_pred(self, position, x_e, y_e, pred);
+                bound & !pred_assert
+            },
+            SubCondition::Id { pred, x_e, y_e, via } | SubCondition::Not { pred, x_e, y_e, via } => {
+                let positive = matches!(sub_condition, SubCondition::Id { .. });
+                position.any(self.problem.targets(x_e, y_e, via).into_iter()
+                    .map(|((ax, ay), args, (x, y))| {
+                        let pred_assert = self.board.gen_static_pred(self, x as usize, y as usize, pred);
+                        position.at(ax, ay) & position.args_are(&args) & if positive { pred_assert } else { !pred_assert }
+                    }))
             },
         }
     }
 
-    fn gen_condition(&self, condition: &Condition, x: &BitVector, y: &BitVector) -> Formula {
-        condition.sub_cond.iter().map(|sub_condition| self.gen_subcondition(*sub_condition, x, y))
-            .reduce(|a, b| a & b)
-            .unwrap_or(!!self.truth)
-    }
-
-    fn gen_static_condition(&self, condition: &Condition, x: i64, y: i64) -> Option<Formula> {
-        condition.sub_cond.iter().map(|sub_condition| self.gen_static_subcondition(*sub_condition, x, y))
-            .reduce(|a, b| Some(a? & b?))
-            .unwrap_or(Some(!!self.truth))
+    fn gen_condition(&self, condition: &Condition, position: &Position) -> Formula {
+        match condition {
+            Condition::Atom(sub_condition) => self.gen_subcondition(sub_condition, position),
+            Condition::Value { variable, value } => self.board.gen_value(self, variable, *value),
+            Condition::And(conditions) => conditions.iter().map(|c| self.gen_condition(c, position))
+                .reduce(|a, b| a & b)
+                .unwrap_or(!!self.truth),
+            Condition::Or(conditions) => conditions.iter().map(|c| self.gen_condition(c, position))
+                .reduce(|a, b| a | b)
+                .unwrap_or(!self.truth),
+            Condition::Not(condition) => !self.gen_condition(condition, position),
+        }
     }
 
     fn gen_goals(&self, goals: &[Condition]) -> Formula {
-        let x_sz = (2 * self.size.x - 1).ilog2();
-        let y_sz = (2 * self.size.y - 1).ilog2();
-        let x = BitVector::new(x_sz as usize);
-        let y = BitVector::new(y_sz as usize);
+        let position = self.position(0);
         let formula = goals.iter()
-            .map(|condition| self.gen_condition(condition, &x, &y))
+            .map(|condition| self.gen_condition(condition, &position))
             .reduce(|a, b| a | b)
             .unwrap_or(!self.truth);
-        x.exists(y.exists(formula))
+        position.exists(position.valid() & formula)
     }
 
-    fn gen_static_goals(&self, goals: &[Condition]) -> Formula {
-        (0..self.size.x as usize).flat_map(|x| repeat(x).zip(0..self.size.y as usize))
-            .flat_map(|(x, y)| repeat((x, y)).zip(goals))
-            .filter_map(|((x, y), condition)| self.gen_static_condition(condition, x as i64, y as i64))
+    fn gen_static_goals(&self, lines: &[Line]) -> Formula {
+        lines.iter()
+            .map(|line| line.iter()
+                .map(|literal| match literal {
+                    Literal::Cell { x, y, pred, positive } => {
+                        let pred = self.board.gen_static_pred(self, *x as usize, *y as usize, pred);
+                        if *positive { pred } else { !pred }
+                    },
+                    Literal::Value { variable, value, positive } => {
+                        let value = self.board.gen_value(self, variable, *value);
+                        if *positive { value } else { !value }
+                    },
+                })
+                .reduce(|a, b| a & b)
+                .unwrap_or(!!self.truth))
             .reduce(|a, b| a | b)
             .unwrap_or(!self.truth)
     }
 
-    fn effect_action(&self, actions: &[Action], x: &BitVector, y: &BitVector, tpe: &BitVector) -> (Formula, SymbolicBoard) {
+    fn effect_action(&self, actions: &[Action], position: &Position, tpe: &BitVector) -> (Formula, SymbolicBoard) {
         let effects: Vec<Effect> = actions.iter()
             .enumerate()
-            .flat_map(|(idx, action)| action.effect.sub_cond.iter()
-                .map(move |cond| match cond {
-                    SubCondition::Id { pred, x_e, y_e } => Effect { x: *x_e, y: *y_e, pred: *pred, tpe: idx as _ },
-                    _ => panic!("Cannot not as an effect"),
+            .flat_map(|(idx, action)| action.effect.iter()
+                .map(move |cond| {
+                    let (SubCondition::Id { pred, x_e, y_e, via } | SubCondition::Not { pred, x_e, y_e, via }) = cond;
+                    Effect {
+                        x: x_e.clone(),
+                        y: y_e.clone(),
+                        targets: (!via.is_empty()).then(|| self.problem.targets(x_e, y_e, via)),
+                        pred: pred.clone(),
+                        delete: matches!(cond, SubCondition::Not { .. }),
+                        tpe: idx as _,
+                    }
                 })
             )
             .collect();
-        self.board.effect(self, &effects, x, y, tpe)
+        let (effect, mut board) = self.board.effect(self, &effects, position, tpe);
+        let (assign, values) = self.board.assign(self, actions, tpe);
+        board.values = values;
+        (effect & assign, board)
     }
 
-    fn solve_black(&mut self, depth: u64) -> Formula {
-        if depth == 0 {
-            return !self.truth
+    fn gen_valid(&self, actions: &[Action], position: &Position, tpe: &BitVector) -> Formula {
+        if actions.is_empty() {
+            return !self.truth;
         }
-        let black_actions = &self.domain.black_actions;
-        let x_sz = (2 * self.size.x - 1).ilog2();
-        let y_sz = (2 * self.size.y - 1).ilog2();
-        let tpe_sz = (2 * black_actions.len() - 1).ilog2();
-        let x = BitVector::new(x_sz as usize);
-        let y = BitVector::new(y_sz as usize);
-        let tpe = BitVector::new(tpe_sz.max(1) as usize);
-        let (effect, new_board) = self.effect_action(black_actions, &x, &y, &tpe);
-        let valid = black_actions.iter()
+        actions.iter()
             .enumerate()
             .map(|(idx, action)| tpe.equal(idx as u64)
-                .implies(self.gen_condition(&action.precondition, &x, &y)))
-            .reduce(|a, b| a & b)
-            .unwrap_or(!!self.truth);
+                .implies(self.gen_condition(&action.precondition, position)))
+            .fold(position.valid() & tpe.le(actions.len() as u64 - 1), |a, b| a & b)
+    }
+
+    /// No action is applicable on the current board. Quantifies fresh atoms on every call, so
+    /// the result may be used more than once.
+    fn gen_stuck(&self, actions: &[Action]) -> Formula {
+        let position = self.position(arity(actions));
+        let tpe = action_vector(actions);
+        let valid = self.gen_valid(actions, &position, &tpe);
+        position.forall(tpe.forall(!valid))
+    }
+
+    /// Constraint that the move is played on the current board, together with the board after it.
+    fn gen_move(&self, actions: &[Action], position: &Position, tpe: &BitVector) -> (Formula, SymbolicBoard) {
+        let (effect, new_board) = self.effect_action(actions, position, tpe);
+        let play = effect & self.gen_valid(actions, position, tpe);
+        let play = match self.problem.stuck {
+            Stuck::Loss | Stuck::Draw => play,
+            // A move already rules out being stuck, so the stuck check only occurs positively.
+            Stuck::Pass => play | self.gen_stuck(actions) & self.board.unchanged(&new_board),
+        };
+        (play, new_board)
+    }
+
+    /// Black wins with `depth` moves left, whoever the turn order gives the next one to.
+    fn solve_next(&mut self, depth: u64, status: Status) -> Formula {
+        match self.problem.turns.mover(self.problem.depth - depth) {
+            Player::Black => self.solve_black(depth, status),
+            Player::White => self.solve_white(depth, status),
+        }
+    }
+
+    fn solve_black(&mut self, depth: u64, status: Status) -> Formula {
+        if depth == 0 {
+            return !!status.won
+        }
+        let black_actions = &self.domain.black_actions;
+        let position = self.position(arity(black_actions));
+        let tpe = action_vector(black_actions);
+        let (play, new_board) = self.gen_move(black_actions, &position, &tpe);
         let previous = std::mem::replace(&mut self.board, new_board);
-        let goal = if self.flatten { self.gen_static_goals(&self.problem.black_goals) } else { self.gen_goals(&self.problem.black_goals) };
-        let wins = self.solve_white(depth - 1);
+        let goal = if self.options.flatten { self.gen_static_goals(&self.black_lines) } else { self.gen_goals(&self.problem.black_goals) };
+        let won = atom();
+        let wins = self.solve_next(depth - 1, Status { won, ..status });
         let new_board = std::mem::replace(&mut self.board, previous);
-        new_board.exists(x.exists(y.exists(tpe.exists(effect & valid & (wins | goal)))))
+        let step = (!!won).implies(!!status.won | !status.over() & goal) & (!status.over()).implies(play);
+        new_board.exists(position.exists(tpe.exists(won.exists(step & wins))))
     }
 
-    fn solve_white(&mut self, depth: u64) -> Formula {
+    fn solve_white(&mut self, depth: u64, status: Status) -> Formula {
         if depth == 0 {
-            return !self.truth
+            return !!status.won
         }
         let white_actions = &self.domain.white_actions;
-        let x_sz = (2 * self.size.x - 1).ilog2();
-        let y_sz = (2 * self.size.y - 1).ilog2();
-        let tpe_sz = (2 * white_actions.len() - 1).ilog2();
-        let x = BitVector::new(x_sz as usize);
-        let y = BitVector::new(y_sz as usize);
-        let tpe = BitVector::new(tpe_sz.max(1) as usize);
-        let (effect, new_board) = self.effect_action(white_actions, &x, &y, &tpe);
-        let valid = white_actions.iter()
-            .enumerate()
-            .map(|(idx, action)| tpe.equal(idx as u64)
-                .implies(self.gen_condition(&action.precondition, &x, &y)))
-            .reduce(|a, b| a & b)
-            .unwrap_or(!!self.truth);
+        let position = self.position(arity(white_actions));
+        let tpe = action_vector(white_actions);
+        let (play, new_board) = self.gen_move(white_actions, &position, &tpe);
+        let draw = match self.problem.stuck {
+            Stuck::Draw => (!status.over()).implies(!self.gen_stuck(white_actions)),
+            Stuck::Loss | Stuck::Pass => !!self.truth,
+        };
         let previous = std::mem::replace(&mut self.board, new_board);
-        let goal = if self.flatten { self.gen_static_goals(&self.problem.white_goals) } else { self.gen_goals(&self.problem.white_goals) };
-        let wins = self.solve_black(depth - 1);
+        let goal = if self.options.flatten { self.gen_static_goals(&self.white_lines) } else { self.gen_goals(&self.problem.white_goals) };
+        let lost = atom();
+        let wins = self.solve_next(depth - 1, Status { lost, ..status });
         let new_board = std::mem::replace(&mut self.board, previous);
-        new_board.forall(x.forall(y.forall(tpe.forall(((effect & valid) | !self.truth).implies(wins & !goal)))))
+        let step = (!!lost).implies(!!status.lost | !status.over() & goal) & (!status.over()).implies(play);
+        new_board.forall(position.forall(tpe.forall(lost.forall(step.implies(wins))))) & draw
     }
 }
 
 
+/// The extra parameters a move needs, shared by the actions.
+fn arity(actions: &[Action]) -> usize {
+    actions.iter().map(|action| action.parameters.len()).max().unwrap_or(0)
+}
+
+/// Selects one of the actions, with spare values ruled out by `gen_valid`.
+fn action_vector(actions: &[Action]) -> BitVector {
+    BitVector::new((2 * actions.len().max(1) - 1).ilog2().max(1) as usize)
+}
+
 struct SymbolicBoard {
     size: Size,
-    // First atom indicates open and second indicates black
-    symbols: Vec<Vec<(Atom, Atom)>>,
+    /// The state of each cell, see `Context::pred_to_atoms`.
+    symbols: Vec<Vec<Vec<Atom>>>,
+    values: Vec<BitVector>,
 }
 
 struct Effect {
     x: E,
     y: E,
+    /// Anchors with the cell they reach, for effects following neighbour relations.
+    targets: Option<Vec<(Cell, Vec<i64>, Cell)>>,
     pred: Pred,
+    /// Opens the cell if it holds `pred` instead of setting it to `pred`.
+    delete: bool,
     tpe: u64,
 }
 
-fn tuple_eq(a: (Atom, Atom), b: (Atom, Atom)) -> Formula {
-    a.0.equal(b.0) & a.1.equal(b.1)
+impl Effect {
+    fn reaches(&self, position: &Position, x: i64, y: i64) -> bool {
+        match &self.targets {
+            None => position.reaches(&self.x, &self.y, x, y),
+            Some(targets) => targets.iter().any(|(_, _, target)| *target == (x, y)),
+        }
+    }
+
+    /// The anchor is one from which the effect lands on `(x, y)`.
+    fn lands_on(&self, position: &Position, x: i64, y: i64) -> Formula {
+        match &self.targets {
+            None => position.offset_is(&self.x, &self.y, x, y),
+            Some(targets) => position.any(targets.iter()
+                .filter(|(_, _, target)| *target == (x, y))
+                .map(|((ax, ay), args, _)| position.at(*ax, *ay) & position.args_are(args))),
+        }
+    }
+}
+
+fn cell_eq(a: &[Atom], b: &[Atom]) -> Formula {
+    a.iter().zip(b)
+        .map(|(a, b)| a.equal(*b))
+        .reduce(|a, b| a & b)
+        .expect("a state has atoms")
 }
 
 impl SymbolicBoard {
-    fn gen_pred(&self, context: &Context, x: &BitVector, x_e: &E, y: &BitVector, y_e: &E, pred: Pred) -> Formula {
-        let (o, b) = context.pred_to_atoms(pred);
+    fn gen_pred(&self, context: &Context, position: &Position, x_e: &E, y_e: &E, pred: &Pred) -> Formula {
+        let state = context.pred_to_atoms(pred);
         (0..self.size.x as usize).flat_map(|x| repeat(x).zip(0..self.size.y as usize))
             .map(|(xi, yi)| 
-                 (context.gen_e_bv_eq(x_e, x, xi as u64, self.size.x as u64) & context.gen_e_bv_eq(y_e, y, yi as u64, self.size.y as u64))
-                    .implies(o.equal(self.symbols[xi][yi].0) & b.equal(self.symbols[xi][yi].1)))
+                 position.offset_is(x_e, y_e, xi as i64, yi as i64)
+                    .implies(cell_eq(&state, &self.symbols[xi][yi])))
             .reduce(|a, b| a & b)
             .expect("board size is not zero")
     }
 
-    fn gen_static_pred(&self, context: &Context, x: usize, y: usize, pred: Pred) -> Formula {
-        let (o, b) = context.pred_to_atoms(pred);
-        o.equal(self.symbols[x][y].0) & b.equal(self.symbols[x][y].1)
+    fn unchanged(&self, next: &SymbolicBoard) -> Formula {
+        self.symbols.iter().flatten().zip(next.symbols.iter().flatten())
+            .chain(self.values.iter().zip(&next.values).map(|(a, b)| (&a.bits, &b.bits)))
+            .map(|(a, b)| cell_eq(a, b))
+            .reduce(|a, b| a & b)
+            .expect("board is not zero size")
+    }
+
+    fn gen_static_pred(&self, context: &Context, x: usize, y: usize, pred: &Pred) -> Formula {
+        cell_eq(&context.pred_to_atoms(pred), &self.symbols[x][y])
     }
 
     fn init(context: &Context, initpreds: &[InitPred], size: Size) -> SymbolicBoard {
-        let mut symbols = vec![vec![context.pred_to_atoms(Pred::Open); size.y as usize]; size.x as usize]; 
+        let mut symbols = vec![vec![context.pred_to_atoms(&Pred::Open); size.y as usize]; size.x as usize];
         for initpred in initpreds {
-            symbols[initpred.x as usize][initpred.y as usize] = context.pred_to_atoms(initpred.pred);
+            symbols[initpred.x as usize][initpred.y as usize] = context.pred_to_atoms(&initpred.pred);
+        }
+        let values = context.domain.initial_values(&context.problem).into_iter()
+            .zip(&context.domain.variables)
+            .map(|(value, variable)| BitVector { bits: context.constant(value, context.value_width(variable)) })
+            .collect();
+        SymbolicBoard { size, symbols, values }
+    }
+
+    /// The variable has the value, never one out of its range.
+    fn gen_value(&self, context: &Context, variable: &str, value: i64) -> Formula {
+        let index = context.domain.variable(variable);
+        if (0..=context.domain.variables[index].max).contains(&value) {
+            self.values[index].equal(value as u64)
+        } else {
+            !context.truth
         }
-        SymbolicBoard { size, symbols }
     }
 
-    fn rec_effect(&self, context: &Context, effects: &[Effect], x: &BitVector, y: &BitVector, tpe: &BitVector, next: (Atom, Atom), xi: usize, yi: usize) -> Formula {
+    /// The variables after the action `tpe` selects, together with them.
+    fn assign(&self, context: &Context, actions: &[Action], tpe: &BitVector) -> (Formula, Vec<BitVector>) {
+        let values: Vec<BitVector> = self.values.iter().map(|before| BitVector::new(before.bits.len())).collect();
+        let formula = context.domain.variables.iter()
+            .zip(self.values.iter().zip(&values))
+            .flat_map(|(variable, (before, next))| actions.iter().enumerate().map(move |(idx, action)| {
+                let after = match action.assignments.iter().rev().find(|a| a.variable() == variable.name) {
+                    None => cell_eq(&before.bits, &next.bits),
+                    Some(assignment) => (0..=variable.max)
+                        .map(|v| before.equal(v as u64).implies(next.equal(assignment.apply(v, variable.max) as u64)))
+                        .reduce(|a, b| a & b)
+                        .expect("a variable has a value"),
+                };
+                tpe.equal(idx as u64).implies(after)
+            }))
+            .fold(!!context.truth, |a, b| a & b);
+        (formula, values)
+    }
+
+    /// The effect changes the cell `(xi, yi)`, and what it changes it to.
+    fn fires(&self, context: &Context, effect: &Effect, position: &Position, tpe: &BitVector, xi: usize, yi: usize) -> (Formula, Pred) {
+        let fire = tpe.equal(effect.tpe) & effect.lands_on(position, xi as i64, yi as i64);
+        if effect.delete {
+            (fire & self.gen_static_pred(context, xi, yi, &effect.pred), Pred::Open)
+        } else {
+            (fire, effect.pred.clone())
+        }
+    }
+
+    /// Checks the effects from the last, so the last one firing decides.
+    fn rec_effect(&self, context: &Context, effects: &[Effect], position: &Position, tpe: &BitVector, next: &[Atom], xi: usize, yi: usize) -> Formula {
         match effects {
-            [] => tuple_eq(self.symbols[xi][yi], next),
-            [hd, ..] => {
-                let tpe_eq = tpe.equal(hd.tpe);
-                let x_bound = context.gen_bounds_check(&hd.x, x, self.size.x as u64);
-                let y_bound = context.gen_bounds_check(&hd.y, y, self.size.y as u64);
-                let x_eq = context.gen_e_bv_eq(&hd.x, x, xi as u64, self.size.x as u64);
-                let y_eq = context.gen_e_bv_eq(&hd.y, y, yi as u64, self.size.y as u64);
-                let then = tuple_eq(next, context.pred_to_atoms(hd.pred));
-                let otherwise = self.rec_effect(context, &effects[1..], x, y, tpe, next, xi, yi);
-                (tpe_eq & x_bound & y_bound & x_eq & y_eq).ite(then, otherwise)
+            [] => cell_eq(&self.symbols[xi][yi], next),
+            [rest @ .., last] => {
+                let (fire, pred) = self.fires(context, last, position, tpe, xi, yi);
+                let then = cell_eq(next, &context.pred_to_atoms(&pred));
+                let otherwise = self.rec_effect(context, rest, position, tpe, next, xi, yi);
+                fire.ite(then, otherwise)
             }
         }
     }
 
-    fn effect(&self, context: &Context, effects: &[Effect], x: &BitVector, y: &BitVector, tpe: &BitVector) -> (Formula, SymbolicBoard) {
-        let symbols = (0..self.size.x).map(|_| (0..self.size.y).map(|_| (atom(), atom())).collect()).collect();
-        let board = SymbolicBoard { size: self.size, symbols };
+    fn frame_effect(&self, context: &Context, effects: &[Effect], position: &Position, tpe: &BitVector, next: &[Atom], xi: usize, yi: usize) -> Formula {
+        let fires: Vec<(Formula, Pred)> = effects.iter()
+            .filter(|hd| hd.reaches(position, xi as i64, yi as i64))
+            .map(|hd| self.fires(context, hd, position, tpe, xi, yi))
+            .collect();
+        let frame = fires.iter()
+            .map(|(fire, _)| fire.clone())
+            .fold(cell_eq(&self.symbols[xi][yi], next), |a, b| a | b);
+        fires.iter()
+            .enumerate()
+            .map(|(i, (fire, pred))| {
+                let last = match fires[i + 1..].iter().map(|(later, _)| later.clone()).reduce(|a, b| a | b) {
+                    Some(overridden) => fire.clone() & !overridden,
+                    None => fire.clone(),
+                };
+                last.implies(cell_eq(next, &context.pred_to_atoms(pred)))
+            })
+            .fold(frame, |a, b| a & b)
+    }
+
+    fn effect(&self, context: &Context, effects: &[Effect], position: &Position, tpe: &BitVector) -> (Formula, SymbolicBoard) {
+        let symbols = (0..self.size.x).map(|_| (0..self.size.y).map(|_| (0..context.width()).map(|_| atom()).collect()).collect()).collect();
+        let board = SymbolicBoard { size: self.size, symbols, values: Vec::new() };
         let formula = (0..self.size.x as usize).flat_map(|x| repeat(x).zip(0..self.size.y as usize))
-            .map(|(xi, yi)| self.rec_effect(context, effects, x, y, tpe, board.symbols[xi][yi], xi, yi))
+            .map(|(xi, yi)| match context.options.transition {
+                Transition::Chained => self.rec_effect(context, effects, position, tpe, &board.symbols[xi][yi], xi, yi),
+                Transition::Frame => self.frame_effect(context, effects, position, tpe, &board.symbols[xi][yi], xi, yi),
+            })
             .reduce(|a, b| a & b)
             .expect("board is not zero size");
         (formula, board)
@@ -252,13 +446,17 @@ impl SymbolicBoard {
 
     fn exists(&self, formula: Formula) -> Formula {
         self.symbols.iter()
-            .flat_map(identity)
-            .fold(formula, |acc, (a, b)| a.exists(b.exists(acc)))
+            .flatten()
+            .flatten()
+            .chain(self.values.iter().flat_map(|value| &value.bits))
+            .fold(formula, |acc, a| a.exists(acc))
     }
 
     fn forall(&self, formula: Formula) -> Formula {
         self.symbols.iter()
-            .flat_map(identity)
-            .fold(formula, |acc, (a, b)| a.forall(b.forall(acc)))
+            .flatten()
+            .flatten()
+            .chain(self.values.iter().flat_map(|value| &value.bits))
+            .fold(formula, |acc, a| a.forall(acc))
     }
 }
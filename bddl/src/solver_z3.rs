@@ -1,18 +1,30 @@
 use std::iter::repeat;
 
-use z3::{Context, ast::{BV, Bool, Ast, Dynamic, forall_const, Datatype, exists_const}, DatatypeBuilder, DatatypeSort};
+use z3::{Context, ast::{BV, Bool, Ast, Dynamic, forall_const, Datatype, exists_const}, DatatypeBuilder, DatatypeSort, Model};
 
-use crate::bddl::{InitPred, Pred, SubCondition, Size, Condition, Problem, Domain, E, Action};
+use crate::{bddl::{InitPred, Pred, SubCondition, Size, Condition, Problem, Domain, E, Action, Stuck, Cell, Variable, Player}, goals::{self, Line, Literal}, solver::{Board, FirstMove, PASS}};
 
-fn gen_bounds_check<'ctx>(e: &E, v: &BV<'ctx>, max: i64) -> Bool<'ctx> {
+fn gen_bounds_check<'ctx>(e: &E, v: &BV<'ctx>, params: &[BV<'ctx>], max: i64) -> Bool<'ctx> {
     match e {
+        E::Add(o) if *o >= max => Bool::from_bool(v.get_ctx(), false),
         E::Add(o) => v.bvule(&BV::from_i64(v.get_ctx(), max - o - 1, v.get_size())),
+        E::Sub(o) if *o >= max => Bool::from_bool(v.get_ctx(), false),
         E::Sub(o) => v.bvuge(&BV::from_i64(v.get_ctx(), *o, v.get_size())),
-        _ => Bool::from_bool(v.get_ctx(), true),
+        E::Int(i) => Bool::from_bool(v.get_ctx(), 0 <= *i && *i < max),
+        E::Identity | E::Min | E::Max => Bool::from_bool(v.get_ctx(), true),
+        E::Param { index, offset, .. } => {
+            let p = &params[*index];
+            let (lo, hi) = ((-offset).max(0), (max - 1 - offset).min((1 << p.get_size()) - 1));
+            if hi < lo {
+                return Bool::from_bool(v.get_ctx(), false);
+            }
+            Bool::and(v.get_ctx(), &[&p.bvuge(&BV::from_i64(v.get_ctx(), lo, p.get_size())), &p.bvule(&BV::from_i64(v.get_ctx(), hi, p.get_size()))])
+        },
     }
 }
 
-fn e_to_bv<'ctx>(e: &E, x: &BV<'ctx>, sz: i64) -> BV<'ctx> {
+/// The coordinate `e` on the axis of `x`, truncated to its width.
+fn e_to_bv<'ctx>(e: &E, x: &BV<'ctx>, params: &[BV<'ctx>], sz: i64) -> BV<'ctx> {
     match e {
         E::Add(o) => x + BV::from_i64(x.get_ctx(), *o, x.get_size()),
         E::Sub(o) => x - BV::from_i64(x.get_ctx(), *o, x.get_size()),
@@ -20,24 +32,42 @@ fn e_to_bv<'ctx>(e: &E, x: &BV<'ctx>, sz: i64) -> BV<'ctx> {
         E::Identity => x.clone(),
         E::Min => BV::from_i64(x.get_ctx(), 0, x.get_size()),
         E::Max => BV::from_i64(x.get_ctx(), sz - 1, x.get_size()),
+        // Parameters are at least as wide as an axis, and in bounds the sum fits the axis.
+        E::Param { index, offset, .. } => {
+            let p = &params[*index];
+            let p = if p.get_size() > x.get_size() { p.extract(x.get_size() - 1, 0) } else { p.clone() };
+            p + BV::from_i64(x.get_ctx(), *offset, x.get_size())
+        },
     }
 }
 
-fn gen_coor_bounds<'ctx>(ex: &E, ey: &E, size: Size, x: &BV<'ctx>, y: &BV<'ctx>) -> Bool<'ctx> {
-    Bool::and(x.get_ctx(), &[&gen_bounds_check(ex, x, size.x), &gen_bounds_check(ey, y, size.y)])
+fn gen_coor_bounds<'ctx>(ex: &E, ey: &E, size: Size, x: &BV<'ctx>, y: &BV<'ctx>, params: &[BV<'ctx>]) -> Bool<'ctx> {
+    Bool::and(x.get_ctx(), &[&gen_bounds_check(ex, x, params, size.x), &gen_bounds_check(ey, y, params, size.y)])
 }
 
-struct Effect<'ctx> {
-    x: &'ctx E,
-    y: &'ctx E,
-    pred: &'ctx Dynamic<'ctx>,
+struct Effect<'a, 'ctx> {
+    x: &'a E,
+    y: &'a E,
+    /// See `solver_qbf::Effect`.
+    targets: Option<Vec<(Cell, Vec<i64>, Cell)>>,
+    pred: &'a Dynamic<'ctx>,
+    /// For deletions, the piece the cell has to hold for `pred` to replace it.
+    deletes: Option<&'a Dynamic<'ctx>>,
     tpe: i64,
 }
 
+/// The anchor `(x, y)` is the cell `(ax, ay)` and the extra parameters start with `args`.
+fn anchor_is<'ctx>(x: &BV<'ctx>, y: &BV<'ctx>, params: &[BV<'ctx>], ax: i64, ay: i64, args: &[i64]) -> Bool<'ctx> {
+    let mut all = vec![x._eq(&BV::from_i64(x.get_ctx(), ax, x.get_size())), y._eq(&BV::from_i64(y.get_ctx(), ay, y.get_size()))];
+    all.extend(params.iter().zip(args).map(|(p, v)| p._eq(&BV::from_i64(p.get_ctx(), *v, p.get_size()))));
+    Bool::and(x.get_ctx(), &all.iter().collect::<Vec<_>>())
+}
+
 struct SymbolicBoard<'ctx> {
     prefix: String,
     size: Size,
     symbols: Vec<Vec<Dynamic<'ctx>>>,
+    values: Vec<BV<'ctx>>,
 }
 
 impl<'ctx> SymbolicBoard<'ctx> {
@@ -56,238 +86,444 @@ impl<'ctx> SymbolicBoard<'ctx> {
         self.symbols[x as usize][y as usize]._eq(pred)
     }
 
-    fn init(initpreds: &[InitPred], size: Size, solver: &'ctx Solver) -> (Self, Bool<'ctx>) {
+    fn init(initpreds: &[InitPred], values: &[i64], size: Size, solver: &Solver<'_, 'ctx>) -> (Self, Bool<'ctx>) {
         let symbols: Vec<Vec<Dynamic<'ctx>>> = (0..size.x)
             .map(|x| (0..size.y).map(|y| Datatype::new_const(&solver.ctx, format!("x{}y{}", x, y), &solver.pred_datatype.sort).into()).collect())
             .collect();
-        let all = (0..symbols.len()).flat_map(|x| repeat(x).zip(0..symbols[0].len()))
+        let mut all = (0..symbols.len()).flat_map(|x| repeat(x).zip(0..symbols[0].len()))
             .map(|(xid, yid)| {
                 let pred = initpreds.iter().find(|i| i.x == xid as _ && i.y == yid as _);
                 let z3_pred = match pred {
-                    Some(v) => solver.pred_to_z3(v.pred),
-                    None => &solver.open,
+                    Some(v) => solver.pred_to_z3(&v.pred),
+                    None => solver.pred_to_z3(&Pred::Open),
                 };
                 symbols[xid][yid]._eq(z3_pred)
             })
             .collect::<Vec<_>>();
+        let (values, initial): (Vec<_>, Vec<_>) = solver.domain.variables.iter().zip(values)
+            .enumerate()
+            .map(|(i, (variable, value))| {
+                let symbol = BV::new_const(solver.ctx, format!("v{}", i), solver.value_width(variable));
+                let initial = symbol._eq(&BV::from_i64(solver.ctx, *value, symbol.get_size()));
+                (symbol, initial)
+            })
+            .unzip();
+        all.extend(initial);
 
         let this = Self {
             prefix: String::new(),
             symbols,
             size,
+            values,
         };
         (this, Bool::and(&solver.ctx, &all.iter().collect::<Vec<_>>()))
     }
 
-    fn rec_effect(&self, effects: &[Effect<'ctx>], x: &BV<'ctx>, y: &BV<'ctx>, tpe: &BV<'ctx>, symbol: &Dynamic<'ctx>, xid: usize, yid: usize, size: Size) -> Bool<'ctx> {
+    fn rec_effect(&self, effects: &[Effect<'_, 'ctx>], x: &BV<'ctx>, y: &BV<'ctx>, params: &[BV<'ctx>], tpe: &BV<'ctx>, symbol: &Dynamic<'ctx>, xid: usize, yid: usize, size: Size) -> Bool<'ctx> {
         match effects {
             [] => self.symbols[xid][yid]._eq(symbol),
-            [hd, ..] => {
+            [rest @ .., hd] => {
                 let tpe_ = tpe._eq(&BV::from_i64(tpe.get_ctx(), hd.tpe, tpe.get_size()));
-                let x_ = e_to_bv(&hd.x, x, self.size.x)._eq(&BV::from_i64(x.get_ctx(), xid as _, x.get_size()));
-                let y_ = e_to_bv(&hd.y, y, self.size.x)._eq(&BV::from_i64(y.get_ctx(), yid as _, y.get_size()));
-                Bool::and(symbol.get_ctx(), &[&gen_coor_bounds(&hd.x, &hd.y, size, x, y), &tpe_, &x_, &y_]).ite(&symbol._eq(&hd.pred), &self.rec_effect(&effects[1..], x, y, tpe, symbol, xid, yid, size))
+                let target = match &hd.targets {
+                    None => {
+                        let x_ = e_to_bv(hd.x, x, params, self.size.x)._eq(&BV::from_i64(x.get_ctx(), xid as _, x.get_size()));
+                        let y_ = e_to_bv(hd.y, y, params, self.size.y)._eq(&BV::from_i64(y.get_ctx(), yid as _, y.get_size()));
+                        Bool::and(symbol.get_ctx(), &[&gen_coor_bounds(hd.x, hd.y, size, x, y, params), &x_, &y_])
+                    },
+                    Some(targets) => {
+                        let anchors = targets.iter()
+                            .filter(|(_, _, target)| *target == (xid as i64, yid as i64))
+                            .map(|((ax, ay), args, _)| anchor_is(x, y, params, *ax, *ay, args))
+                            .collect::<Vec<_>>();
+                        Bool::or(symbol.get_ctx(), &anchors.iter().collect::<Vec<_>>())
+                    },
+                };
+                let held = match hd.deletes {
+                    Some(piece) => self.symbols[xid][yid]._eq(piece),
+                    None => Bool::from_bool(symbol.get_ctx(), true),
+                };
+                Bool::and(symbol.get_ctx(), &[&tpe_, &target, &held]).ite(&symbol._eq(hd.pred), &self.rec_effect(rest, x, y, params, tpe, symbol, xid, yid, size))
             },
         }
     }
 
-    fn effect(&self, effects: &[Effect<'ctx>], x: &BV<'ctx>, y: &BV<'ctx>, tpe: &BV<'ctx>, solver: &Solver<'ctx>) -> (Bool<'ctx>, Self) {
+    fn unchanged(&self, next: &SymbolicBoard<'ctx>) -> Bool<'ctx> {
+        let all = self.symbols.iter().flatten().zip(next.symbols.iter().flatten())
+            .map(|(a, b)| a._eq(b))
+            .chain(self.values.iter().zip(&next.values).map(|(a, b)| a._eq(b)))
+            .collect::<Vec<_>>();
+        Bool::and(next.symbols[0][0].get_ctx(), &all.iter().collect::<Vec<_>>())
+    }
+
+    fn effect(&self, effects: &[Effect<'_, 'ctx>], x: &BV<'ctx>, y: &BV<'ctx>, params: &[BV<'ctx>], tpe: &BV<'ctx>, solver: &Solver<'_, 'ctx>) -> (Bool<'ctx>, Self) {
         let prefix = format!("_{}_", self.prefix);
         let symbols: Vec<Vec<Dynamic<'ctx>>> = (0..self.size.x)
             .map(|x| (0..self.size.y).map(|y| Datatype::new_const(&solver.ctx, format!("{}x{}y{}", prefix, x, y), &solver.pred_datatype.sort).into()).collect())
             .collect();
         let all = (0..symbols.len()).flat_map(|x| repeat(x).zip(0..symbols[0].len()))
             .map(|(xid, yid)| {
-                self.rec_effect(effects, x, y, tpe, &symbols[xid][yid], xid, yid, solver.size)
+                self.rec_effect(effects, x, y, params, tpe, &symbols[xid][yid], xid, yid, solver.size)
             })
             .collect::<Vec<_>>();
         let b = Bool::and(x.get_ctx(), &all.iter().collect::<Vec<_>>());
         let this = Self { 
             prefix, 
             size: self.size, 
-            symbols 
+            symbols,
+            values: Vec::new(),
         };
         (b, this)
     }
+
+    /// The variables after the action `tpe` selects, named with `prefix`, together with them.
+    fn assign(&self, actions: &[Action], tpe: &BV<'ctx>, prefix: &str, solver: &Solver<'_, 'ctx>) -> (Bool<'ctx>, Vec<BV<'ctx>>) {
+        let values: Vec<BV<'ctx>> = self.values.iter().enumerate()
+            .map(|(i, before)| BV::new_const(solver.ctx, format!("{}v{}", prefix, i), before.get_size()))
+            .collect();
+        let all = solver.domain.variables.iter()
+            .zip(self.values.iter().zip(&values))
+            .flat_map(|(variable, (before, next))| actions.iter().enumerate().map(move |(idx, action)| {
+                let constant = |v: i64| BV::from_i64(before.get_ctx(), v, before.get_size());
+                let after = match action.assignments.iter().rev().find(|a| a.variable() == variable.name) {
+                    None => next._eq(before),
+                    Some(assignment) => {
+                        let cases = (0..=variable.max)
+                            .map(|v| before._eq(&constant(v)).implies(&next._eq(&constant(assignment.apply(v, variable.max)))))
+                            .collect::<Vec<_>>();
+                        Bool::and(before.get_ctx(), &cases.iter().collect::<Vec<_>>())
+                    },
+                };
+                tpe._eq(&BV::from_i64(tpe.get_ctx(), idx as _, tpe.get_size())).implies(&after)
+            }))
+            .collect::<Vec<_>>();
+        (Bool::and(tpe.get_ctx(), &all.iter().collect::<Vec<_>>()), values)
+    }
+}
+
+/// Like `solver_qbf::Status`.
+struct Status<'ctx> {
+    won: Bool<'ctx>,
+    lost: Bool<'ctx>,
 }
 
-struct Solver<'ctx> {
+impl<'ctx> Status<'ctx> {
+    fn over(&self) -> Bool<'ctx> {
+        Bool::or(self.won.get_ctx(), &[&self.won, &self.lost])
+    }
+}
+
+/// Bits of a coordinate on an axis of `n` cells.
+fn axis_bits(n: i64) -> u32 {
+    (2 * n - 1).ilog2().max(1)
+}
+
+fn action_bits(actions: &[Action]) -> u32 {
+    (usize::BITS - actions.len().leading_zeros()).max(1)
+}
+
+struct Solver<'a, 'ctx> {
     ctx: &'ctx Context,
+    problem: &'a Problem,
     pred_datatype: DatatypeSort<'ctx>,
-    open: Dynamic<'ctx>,
-    white: Dynamic<'ctx>,
-    black: Dynamic<'ctx>,
-    problem: &'ctx Problem,
-    domain: &'ctx Domain,
+    /// A constructor per state of the domain, numbered like `Domain::states`.
+    states: Vec<Dynamic<'ctx>>,
+    domain: &'a Domain,
     x_sz: u32,
     y_sz: u32,
+    p_sz: u32,
     size: Size,
+    black_lines: Vec<Line>,
+    white_lines: Vec<Line>,
 }
 
-impl<'ctx> Solver<'ctx> {
-    fn new(ctx: &'ctx Context, problem: &'ctx Problem, domain: &'ctx Domain) -> Solver<'ctx> {
-        let pred_datatype = DatatypeBuilder::new(ctx, "Pred")
-            .variant("Open", Vec::new())
-            .variant("White", Vec::new())
-            .variant("Black", Vec::new())
+impl<'a, 'ctx> Solver<'a, 'ctx> {
+    fn new(ctx: &'ctx Context, problem: &'a Problem, domain: &'a Domain) -> Solver<'a, 'ctx> {
+        let pred_datatype = domain.states().iter()
+            .fold(DatatypeBuilder::new(ctx, "Pred"), |builder, pred| builder.variant(&pred.to_string(), Vec::new()))
             .finish();
-        let open = pred_datatype.variants[0].constructor.apply(&[]);
-        let white = pred_datatype.variants[1].constructor.apply(&[]);
-        let black = pred_datatype.variants[2].constructor.apply(&[]);
-        let x_sz = (2 * problem.size.x - 1).ilog2();
-        let y_sz = (2 * problem.size.y - 1).ilog2();
+        let states = pred_datatype.variants.iter().map(|variant| variant.constructor.apply(&[])).collect();
+        let x_sz = axis_bits(problem.size.x);
+        let y_sz = axis_bits(problem.size.y);
+        let p_sz = axis_bits(problem.size.extent());
         let size = problem.size;
         Solver {
             ctx,
-            pred_datatype,
-            open,
-            white,
-            black,
             problem,
+            pred_datatype,
+            states,
             domain,
             x_sz,
             y_sz,
+            p_sz,
             size,
+            black_lines: goals::compile(&problem.black_goals, problem),
+            white_lines: goals::compile(&problem.white_goals, problem),
         }
     }
 
-    fn pred_to_z3(&self, pred: Pred) -> &Dynamic<'ctx> {
-        match pred {
-            Pred::Open => &self.open,
-            Pred::White => &self.white,
-            Pred::Black => &self.black,
+    /// A bit-vector per extra parameter of the actions, wide enough for either axis.
+    fn params(&self, actions: &[Action], prefix: &str) -> Vec<BV<'ctx>> {
+        let arity = actions.iter().map(|action| action.parameters.len()).max().unwrap_or(0);
+        (0..arity).map(|i| BV::new_const(self.ctx, format!("{}p{}", prefix, i), self.p_sz)).collect()
+    }
+
+    fn pred_to_z3(&self, pred: &Pred) -> &Dynamic<'ctx> {
+        &self.states[self.domain.state(pred)]
+    }
+
+    /// A bit-vector wide enough for the values of the variable.
+    fn value_width(&self, variable: &Variable) -> u32 {
+        (2 * variable.max + 1).ilog2().max(1)
+    }
+
+    fn gen_value(&self, variable: &str, value: i64, board: &SymbolicBoard<'ctx>) -> Bool<'ctx> {
+        let index = self.domain.variable(variable);
+        if (0..=self.domain.variables[index].max).contains(&value) {
+            board.values[index]._eq(&BV::from_i64(self.ctx, value, board.values[index].get_size()))
+        } else {
+            Bool::from_bool(self.ctx, false)
         }
     }
 
-    fn gen_pred_assert(&self, x: &BV<'ctx>, y: &BV<'ctx>, pred: Pred, board: &SymbolicBoard<'ctx>) -> Bool<'ctx> {
+    fn gen_pred_assert(&self, x: &BV<'ctx>, y: &BV<'ctx>, pred: &Pred, board: &SymbolicBoard<'ctx>) -> Bool<'ctx> {
         board.pred(x, y, self.pred_to_z3(pred))
     }
 
-    fn gen_subcondition(&self, sub_condition: SubCondition, x: &BV<'ctx>, y: &BV<'ctx>, board: &SymbolicBoard<'ctx>) -> Bool<'ctx> {
+    fn gen_subcondition(&self, sub_condition: &SubCondition, x: &BV<'ctx>, y: &BV<'ctx>, params: &[BV<'ctx>], board: &SymbolicBoard<'ctx>) -> Bool<'ctx> {
         match sub_condition {
-            SubCondition::Id { pred, x_e, y_e } => {
-                let bounds = gen_coor_bounds(&x_e, &y_e, self.size, x, y);
-                Bool::and(self.ctx, &[&self.gen_pred_assert(&e_to_bv(&x_e, x, self.size.x), &e_to_bv(&y_e, y, self.size.y), pred, board), &bounds])
+            SubCondition::Id { pred, x_e, y_e, via } if via.is_empty() => {
+                let bounds = gen_coor_bounds(x_e, y_e, self.size, x, y, params);
+                Bool::and(self.ctx, &[&self.gen_pred_assert(&e_to_bv(x_e, x, params, self.size.x), &e_to_bv(y_e, y, params, self.size.y), pred, board), &bounds])
             },
-            SubCondition::Not { pred, x_e, y_e } => {
-                let bounds = gen_coor_bounds(&x_e, &y_e, self.size, x, y);
-                Bool::and(self.ctx, &[&self.gen_pred_assert(&e_to_bv(&x_e, x, self.size.x), &e_to_bv(&y_e, y, self.size.y), pred, board).not(), &bounds])
+            SubCondition::Not { pred, x_e, y_e, via } if via.is_empty() => {
+                let bounds = gen_coor_bounds(x_e, y_e, self.size, x, y, params);
+                Bool::and(self.ctx, &[&self.gen_pred_assert(&e_to_bv(x_e, x, params, self.size.x), &e_to_bv(y_e, y, params, self.size.y), pred, board).not(), &bounds])
+            },
+            SubCondition::Id { pred, x_e, y_e, via } | SubCondition::Not { pred, x_e, y_e, via } => {
+                let any = self.problem.targets(x_e, y_e, via).into_iter()
+                    .map(|((ax, ay), args, (tx, ty))| {
+                        let pred_assert = board.static_pred(tx, ty, self.pred_to_z3(pred));
+                        let pred_assert = if let SubCondition::Id { .. } = sub_condition { pred_assert } else { pred_assert.not() };
+                        Bool::and(self.ctx, &[&anchor_is(x, y, params, ax, ay, &args), &pred_assert])
+                    })
+                    .collect::<Vec<_>>();
+                Bool::or(self.ctx, &any.iter().collect::<Vec<_>>())
             },
         }
     }
     
-    fn const_gen_subcondition(&self, sub_condition: SubCondition, x: i64, y: i64, board: &SymbolicBoard<'ctx>) -> Option<Bool<'ctx>> {
-        match sub_condition {
-            SubCondition::Id { pred, x_e, y_e } => {
-                Some(board.static_pred(x_e.noramlize(x, self.size.x)?, y_e.noramlize(y, self.size.y)?, self.pred_to_z3(pred)))
-            },
-            SubCondition::Not { pred, x_e, y_e } => self.const_gen_subcondition(SubCondition::Id { pred, x_e, y_e }, x, y, board).as_ref().map(Bool::not),
+    fn gen_condition(&self, condition: &Condition, x: &BV<'ctx>, y: &BV<'ctx>, params: &[BV<'ctx>], board: &SymbolicBoard<'ctx>) -> Bool<'ctx> {
+        let all = |conditions: &[Condition]| conditions.iter()
+            .map(|condition| self.gen_condition(condition, x, y, params, board))
+            .collect::<Vec<Bool<'ctx>>>();
+        match condition {
+            Condition::Atom(sub_condition) => self.gen_subcondition(sub_condition, x, y, params, board),
+            Condition::Value { variable, value } => self.gen_value(variable, *value, board),
+            Condition::And(conditions) => Bool::and(self.ctx, &all(conditions).iter().collect::<Vec<&Bool<'ctx>>>()),
+            Condition::Or(conditions) => Bool::or(self.ctx, &all(conditions).iter().collect::<Vec<&Bool<'ctx>>>()),
+            Condition::Not(condition) => self.gen_condition(condition, x, y, params, board).not(),
         }
     }
 
-    fn gen_condition(&self, condition: &Condition, x: &BV<'ctx>, y: &BV<'ctx>, board: &SymbolicBoard<'ctx>) -> Bool<'ctx> {
-        let all = condition.sub_cond.iter()
-            .map(|sub_condition| self.gen_subcondition(*sub_condition, x, y, board))
+    fn gen_line(&self, line: &Line, board: &SymbolicBoard<'ctx>) -> Bool<'ctx> {
+        let all = line.iter()
+            .map(|literal| match literal {
+                Literal::Cell { x, y, pred, positive } => {
+                    let pred = board.static_pred(*x, *y, self.pred_to_z3(pred));
+                    if *positive { pred } else { pred.not() }
+                },
+                Literal::Value { variable, value, positive } => {
+                    let value = self.gen_value(variable, *value, board);
+                    if *positive { value } else { value.not() }
+                },
+            })
             .collect::<Vec<Bool<'ctx>>>();
         Bool::and(self.ctx, &all.iter().collect::<Vec<&Bool<'ctx>>>())
     }
 
-    fn const_gen_condition(&self, condition: &Condition, x: i64, y: i64, board: &SymbolicBoard<'ctx>) -> Option<Bool<'ctx>> {
-        let all = condition.sub_cond.iter()
-            .map(|sub_condition| self.const_gen_subcondition(*sub_condition, x, y, board))
-            .collect::<Option<Vec<Bool<'ctx>>>>()?;
-        Some(Bool::and(self.ctx, &all.iter().collect::<Vec<&Bool<'ctx>>>()))
-    }
-
-    fn gen_goals(&self, goals: &[Condition], board: &SymbolicBoard<'ctx>) -> Bool<'ctx> {
-        let ors: Vec<_> = (0..self.size.x).flat_map(|x| repeat(x).zip(0..self.size.y))
-            .flat_map(|(x, y)| {
-                goals.iter()
-                    .filter_map(move |condition| {
-                        self.const_gen_condition(condition, x, y, board)
-                    })
-            })
+    fn gen_goals(&self, lines: &[Line], board: &SymbolicBoard<'ctx>) -> Bool<'ctx> {
+        let ors: Vec<_> = lines.iter()
+            .map(|line| self.gen_line(line, board))
             .collect();
         Bool::or(self.ctx, &ors.iter().collect::<Vec<&Bool<'ctx>>>())
     }
 
-    fn effect_action(&'ctx self, actions: &'ctx [Action], x: &BV<'ctx>, y: &BV<'ctx>, tpe: &BV<'ctx>, board: &SymbolicBoard<'ctx>) -> (Bool<'ctx>, SymbolicBoard<'ctx>) {
+    fn effect_action(&self, actions: &[Action], x: &BV<'ctx>, y: &BV<'ctx>, params: &[BV<'ctx>], tpe: &BV<'ctx>, board: &SymbolicBoard<'ctx>) -> (Bool<'ctx>, SymbolicBoard<'ctx>) {
         let effects: Vec<Effect> = actions.iter()
             .enumerate().
-            flat_map(|(idx, action)| action.effect.sub_cond.iter()
-                .map(move |cond| match cond {
-                    SubCondition::Id { pred, x_e, y_e } => Effect { x: x_e, y: y_e, pred: self.pred_to_z3(*pred), tpe: idx as _ },
-                    _ => panic!("Cannot not as an effect"),
+            flat_map(|(idx, action)| action.effect.iter()
+                .map(move |cond| {
+                    let (SubCondition::Id { pred, x_e, y_e, via } | SubCondition::Not { pred, x_e, y_e, via }) = cond;
+                    let deletes = matches!(cond, SubCondition::Not { .. }).then(|| self.pred_to_z3(pred));
+                    Effect {
+                        x: x_e,
+                        y: y_e,
+                        targets: (!via.is_empty()).then(|| self.problem.targets(x_e, y_e, via)),
+                        pred: if deletes.is_some() { self.pred_to_z3(&Pred::Open) } else { self.pred_to_z3(pred) },
+                        deletes,
+                        tpe: idx as _,
+                    }
                 })
             )
             .collect();
-        board.effect(&effects, x, y, tpe, self)
+        let (effect, mut new_board) = board.effect(&effects, x, y, params, tpe, self);
+        let (assign, values) = board.assign(actions, tpe, &new_board.prefix, self);
+        new_board.values = values;
+        (Bool::and(self.ctx, &[&effect, &assign]), new_board)
+    }
+
+    fn gen_valid(&self, actions: &[Action], x: &BV<'ctx>, y: &BV<'ctx>, params: &[BV<'ctx>], tpe: &BV<'ctx>, board: &SymbolicBoard<'ctx>) -> Bool<'ctx> {
+        if actions.is_empty() {
+            return Bool::from_bool(self.ctx, false);
+        }
+        let mut valid_bools = actions.iter()
+            .enumerate()
+            .map(|(idx, action)| 
+                 BV::from_i64(&self.ctx, idx as _, tpe.get_size())
+                    ._eq(tpe)
+                    .implies(&self.gen_condition(&action.precondition, x, y, params, board)))
+            .collect::<Vec<_>>();
+        valid_bools.push(x.bvule(&BV::from_i64(self.ctx, self.size.x - 1, self.x_sz)));
+        valid_bools.push(y.bvule(&BV::from_i64(self.ctx, self.size.y - 1, self.y_sz)));
+        valid_bools.extend(params.iter().map(|p| p.bvule(&BV::from_i64(self.ctx, self.size.extent() - 1, self.p_sz))));
+        valid_bools.push(tpe.bvule(&BV::from_i64(self.ctx, actions.len() as i64 - 1, tpe.get_size())));
+        Bool::and(self.ctx, &valid_bools.iter().collect::<Vec<_>>())
+    }
+
+    /// No action is applicable on `board`.
+    fn gen_stuck(&self, actions: &[Action], board: &SymbolicBoard<'ctx>) -> Bool<'ctx> {
+        let x = BV::new_const(&self.ctx, format!("{}x_s", board.prefix), self.x_sz);
+        let y = BV::new_const(&self.ctx, format!("{}y_s", board.prefix), self.y_sz);
+        let params = self.params(actions, &format!("{}_s", board.prefix));
+        let tpe = BV::new_const(&self.ctx, format!("{}t_s", board.prefix), action_bits(actions));
+        let mut vars: Vec<&dyn Ast> = vec![&x, &y, &tpe];
+        vars.extend(params.iter().map(|p| -> &dyn Ast { p }));
+        forall_const(&self.ctx, &vars, &[], &self.gen_valid(actions, &x, &y, &params, &tpe, board).not())
+    }
+
+    /// Constraint that the move is played on `board`, together with the board after it.
+    fn gen_move(&self, actions: &[Action], x: &BV<'ctx>, y: &BV<'ctx>, params: &[BV<'ctx>], tpe: &BV<'ctx>, board: &SymbolicBoard<'ctx>) -> (Bool<'ctx>, SymbolicBoard<'ctx>) {
+        let (effect, new_board) = self.effect_action(actions, x, y, params, tpe, board);
+        let play = Bool::and(self.ctx, &[&effect, &self.gen_valid(actions, x, y, params, tpe, board)]);
+        let play = match self.problem.stuck {
+            Stuck::Loss | Stuck::Draw => play,
+            Stuck::Pass => Bool::or(self.ctx, &[&play, &Bool::and(self.ctx, &[&self.gen_stuck(actions, board), &board.unchanged(&new_board)])]),
+        };
+        (play, new_board)
+    }
+
+    fn solve_next(&self, board: &SymbolicBoard<'ctx>, depth: u64, status: &Status<'ctx>) -> Bool<'ctx> {
+        match self.problem.turns.mover(self.problem.depth - depth) {
+            Player::Black => self.solve_black(board, depth, status),
+            Player::White => self.solve_white(board, depth, status),
+        }
     }
 
-    fn solve_black(&'ctx self, board: &SymbolicBoard<'ctx>, depth: u64) -> Bool<'ctx> {
+    fn solve_black(&self, board: &SymbolicBoard<'ctx>, depth: u64, status: &Status<'ctx>) -> Bool<'ctx> {
         if depth == 0 {
-            return Bool::from_bool(&self.ctx, false);
+            return status.won.clone();
         }
         let black_actions = &self.domain.black_actions;
-        let tpe_sz = usize::BITS - black_actions.len().leading_zeros();
         let x = BV::new_const(&self.ctx, format!("{}x_x", board.prefix), self.x_sz);
         let y = BV::new_const(&self.ctx, format!("{}y_y", board.prefix), self.y_sz);
-        let tpe = BV::new_const(&self.ctx, format!("{}t_t", board.prefix), tpe_sz);
-        let (effect, new_board) = self.effect_action(black_actions, &x, &y, &tpe, &board);
-        let valid_bools = black_actions.iter()
-            .enumerate()
-            .map(|(idx, action)| 
-                 BV::from_i64(&self.ctx, idx as _, tpe_sz)
-                    ._eq(&tpe)
-                    .implies(&self.gen_condition(&action.precondition, &x, &y, board)))
-            .collect::<Vec<_>>();
-        let valid = Bool::and(self.ctx, &valid_bools.iter().collect::<Vec<_>>());
-        let wins = self.solve_white(&new_board, depth - 1);
-        let goal = self.gen_goals(&self.problem.black_goals, &new_board).simplify();
-        let wins = Bool::or(self.ctx, &[&wins, &goal]);
+        let params = self.params(black_actions, &format!("{}_p", board.prefix));
+        let tpe = BV::new_const(&self.ctx, format!("{}t_t", board.prefix), action_bits(black_actions));
+        let (play, new_board) = self.gen_move(black_actions, &x, &y, &params, &tpe, board);
+        let goal = self.gen_goals(&self.black_lines, &new_board).simplify();
+        let over = status.over();
+        let won = Bool::or(self.ctx, &[&status.won, &Bool::and(self.ctx, &[&over.not(), &goal])]);
+        let wins = self.solve_next(&new_board, depth - 1, &Status { won, lost: status.lost.clone() });
+        let play = over.not().implies(&play);
         let mut vars: Vec<&dyn Ast> = new_board.symbols.iter().flatten().map(|x| -> &dyn Ast { x }).collect();
+        vars.extend(new_board.values.iter().map(|v| -> &dyn Ast { v }));
         vars.push(&x);
         vars.push(&y);
         vars.push(&tpe);
-        exists_const(&self.ctx, &vars, &[], &Bool::and(self.ctx, &[&effect, &valid, &wins]))
+        vars.extend(params.iter().map(|p| -> &dyn Ast { p }));
+        if depth == self.problem.depth {
+            // Black's first move stays free, so that a model shows it, see `first_move`.
+            return Bool::and(self.ctx, &[&play, &wins]);
+        }
+        exists_const(&self.ctx, &vars, &[], &Bool::and(self.ctx, &[&play, &wins]))
     }
     
-    fn solve_white(&'ctx self, board: &SymbolicBoard<'ctx>, depth: u64) -> Bool<'ctx> {
+    fn solve_white(&self, board: &SymbolicBoard<'ctx>, depth: u64, status: &Status<'ctx>) -> Bool<'ctx> {
         if depth == 0 {
-            return Bool::from_bool(&self.ctx, false);
+            return status.won.clone();
         }
         let white_actions = &self.domain.white_actions;
-        let tpe_sz = usize::BITS - white_actions.len().leading_zeros();
         let x = BV::new_const(&self.ctx, format!("{}x_x", board.prefix), self.x_sz);
         let y = BV::new_const(&self.ctx, format!("{}y_y", board.prefix), self.y_sz);
-        let tpe = BV::new_const(&self.ctx, format!("{}t_t", board.prefix), tpe_sz);
-        let (effect, new_board) = self.effect_action(white_actions, &x, &y, &tpe, &board);
-        let valid_bools = white_actions.iter()
-            .enumerate()
-            .map(|(idx, action)| 
-                 BV::from_i64(&self.ctx, idx as _, tpe_sz)
-                    ._eq(&tpe)
-                    .implies(&self.gen_condition(&action.precondition, &x, &y, board)))
-            .collect::<Vec<_>>();
-        let valid = Bool::and(self.ctx, &valid_bools.iter().collect::<Vec<_>>());
-        let wins = self.solve_black(&new_board, depth - 1);
-        let goal = self.gen_goals(&self.problem.white_goals, &new_board);
-        let wins = Bool::and(self.ctx, &[&wins, &goal.not()]);
+        let params = self.params(white_actions, &format!("{}_p", board.prefix));
+        let tpe = BV::new_const(&self.ctx, format!("{}t_t", board.prefix), action_bits(white_actions));
+        let (play, new_board) = self.gen_move(white_actions, &x, &y, &params, &tpe, board);
+        let draw = match self.problem.stuck {
+            Stuck::Draw => status.over().not().implies(&self.gen_stuck(white_actions, board).not()),
+            Stuck::Loss | Stuck::Pass => Bool::from_bool(self.ctx, true),
+        };
+        let goal = self.gen_goals(&self.white_lines, &new_board);
+        let over = status.over();
+        let lost = Bool::or(self.ctx, &[&status.lost, &Bool::and(self.ctx, &[&over.not(), &goal])]);
+        let wins = self.solve_next(&new_board, depth - 1, &Status { won: status.won.clone(), lost });
+        let play = over.not().implies(&play);
         let mut vars: Vec<&dyn Ast> = new_board.symbols.iter().flatten().map(|x| -> &dyn Ast { x }).collect();
+        vars.extend(new_board.values.iter().map(|v| -> &dyn Ast { v }));
         vars.push(&x);
         vars.push(&y);
         vars.push(&tpe);
-        forall_const(&self.ctx, &vars, &[], &Bool::and(self.ctx, &[&effect, &valid]).implies(&wins))
+        vars.extend(params.iter().map(|p| -> &dyn Ast { p }));
+        Bool::and(self.ctx, &[&draw, &forall_const(&self.ctx, &vars, &[], &play.implies(&wins))])
+    }
+}
+
+/// The formula, in `ctx`, that holds iff black has a forced win.
+pub fn solve<'ctx>(ctx: &'ctx Context, problem: &Problem, domain: &Domain) -> Bool<'ctx> {
+    let solver = Solver::new(ctx, problem, domain);
+    let (board, cond) = SymbolicBoard::init(&problem.init, &domain.initial_values(problem), problem.size, &solver);
+    let status = Status { won: Bool::from_bool(ctx, false), lost: Bool::from_bool(ctx, false) };
+    Bool::and(ctx, &[&cond, &solver.solve_next(&board, problem.depth, &status)]).simplify()
+}
+
+/// A formula of `solve` as an SMT-LIB2 script for other SMT solvers: the declarations, the
+/// assertion and `(check-sat)`.
+pub fn smt2(formula: &Bool) -> String {
+    let solver = z3::Solver::new(formula.get_ctx());
+    solver.assert(formula);
+    format!("{}(check-sat)\n", solver)
+}
+
+/// Black's first move in a model of the formula of `solve`, if black moves first.
+pub fn first_move(problem: &Problem, domain: &Domain, ctx: &Context, model: &Model) -> Option<FirstMove> {
+    if problem.turns.mover(0) == Player::White {
+        return None;
+    }
+    let board = Board::initial(problem, domain);
+    if domain.black_actions.iter().all(|action| board.actions(action).is_empty()) {
+        return Some((PASS.to_owned(), 0, 0, Vec::new()));
     }
+    let value = |name: &str, bits: u32| model.eval(&BV::new_const(ctx, name, bits), true).and_then(|v| v.as_u64()).expect("a value in the model");
+    let action = &domain.black_actions[value("t_t", action_bits(&domain.black_actions)) as usize];
+    let args = (0..action.parameters.len()).map(|i| value(&format!("_pp{}", i), axis_bits(problem.size.extent())) as i64).collect();
+    Some((action.name.clone(), value("x_x", axis_bits(problem.size.x)) as i64, value("y_y", axis_bits(problem.size.y)) as i64, args))
 }
 
-pub fn solve<'ctx>(problem: &'ctx Problem, domain: &'ctx Domain) -> impl Fn(&'ctx Context) -> Bool<'ctx> {
-    |ctx| {
-        let solver = Solver::new(ctx, problem, domain);
-        let (board, cond) = SymbolicBoard::init(&problem.init, problem.size, &solver);
-        let ret = Bool::and(ctx, &[&cond, &solver.solve_black(&board, problem.depth)]).simplify();
-        // This is safe due to the c++ api not following rust rules. Ie this only depends on
-        // context. This could be avoided if solver did not store ctx but it was passed around.
-        unsafe { std::mem::transmute(ret) }
+/// Whether black has a forced win, with its first move if black moves first, like
+/// `solver::solve`. Checks on `solver` if given, and fails with the reason z3 gives up for.
+pub fn solve_move(problem: &Problem, domain: &Domain, solver: Option<&z3::Solver>) -> Result<Option<Option<FirstMove>>, String> {
+    let (default_context, default_solver);
+    let solver = match solver {
+        Some(solver) => solver,
+        None => {
+            default_context = Context::new(&Default::default());
+            default_solver = z3::Solver::new(&default_context);
+            &default_solver
+        },
+    };
+    let context = solver.get_context();
+    solver.assert(&solve(context, problem, domain));
+    match solver.check() {
+        z3::SatResult::Sat => Ok(Some(first_move(problem, domain, context, &solver.get_model().expect("a model of a satisfiable formula")))),
+        z3::SatResult::Unsat => Ok(None),
+        z3::SatResult::Unknown => Err(solver.get_reason_unknown().unwrap_or_default()),
     }
 }
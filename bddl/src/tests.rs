@@ -0,0 +1,769 @@
+use std::fmt::{self, Display};
+
+use z3::{Context, SatResult, Solver};
+
+use crate::{bddl::{resolve_init, Domain, Player, Pred, Problem, Size, Turns}, bench, goals, lexer, outcome::{self, Outcome, Query}, parse_domain, parse_problem, parser, play, position::{Position, PositionEncoding}, qbf::{atom, Formula}, record::{self, Record}, render, solver, solver_qbf, solver_z3};
+
+pub fn depqbf_available() -> bool {
+    std::process::Command::new("depqbf")
+        .arg("--version")
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null())
+        .status()
+        .is_ok()
+}
+
+/// SplitMix64, enough to make instances reproducible from their seed.
+pub struct Rng(pub u64);
+
+impl Rng {
+    pub fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform in `lo..=hi`.
+    pub fn range(&mut self, lo: i64, hi: i64) -> i64 {
+        lo + (self.next() % (hi - lo + 1) as u64) as i64
+    }
+
+    pub fn percent(&mut self, p: u64) -> bool {
+        self.next() % 100 < p
+    }
+
+    pub fn pick<T: Clone>(&mut self, items: &[T]) -> T {
+        items[self.next() as usize % items.len()].clone()
+    }
+}
+
+/// Whether black wins, asserting that all available backends agree.
+fn black_wins(problem: &str, domain: &str) -> bool {
+    let problem = parse_problem(problem).unwrap();
+    let domain = parse_domain(domain).unwrap();
+    let explicit = solver::solve(&problem, &domain).is_some();
+    let position = solver::Board::initial(&problem, &domain).to_string();
+
+    let context = Context::new(&Default::default());
+    let solver = Solver::new(&context);
+    solver.assert(&solver_z3::solve(&context, &problem, &domain));
+    assert_eq!(solver.check() == SatResult::Sat, explicit, "z3 disagrees with explicit from\n{position}");
+
+    if depqbf_available() {
+        let qbf = solver_qbf::solve(problem, domain, Default::default()).check();
+        assert_eq!(qbf, explicit, "qbf disagrees with explicit from\n{position}");
+    }
+    explicit
+}
+
+/// The text of a problem, to be varied from `GAME` by struct update.
+struct Game<'a> {
+    /// `#boardsize`, unless there are `positions`.
+    size: &'a str,
+    positions: &'a str,
+    /// Sections relating the `positions`, such as `#neighbours`.
+    neighbours: &'a str,
+    init: &'a str,
+    depth: u64,
+    /// `#turnorder`, left out if empty.
+    turns: &'a str,
+    black_goal: &'a str,
+    white_goal: &'a str,
+    /// `#nomove`, left out if empty.
+    stuck: &'a str,
+}
+
+/// One move on an empty 2x2 board, where either side wins with two pieces in a row.
+const GAME: Game<'static> = Game {
+    size: "2 2",
+    positions: "",
+    neighbours: "",
+    init: "",
+    depth: 1,
+    turns: "",
+    black_goal: "(black(?x,?y) black(?x+1,?y))",
+    white_goal: "(white(?x,?y) white(?x+1,?y))",
+    stuck: "",
+};
+
+impl Display for Game<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.positions.is_empty() {
+            writeln!(f, "#boardsize\n{}", self.size)?;
+        } else {
+            writeln!(f, "#positions\n({})\n{}", self.positions, self.neighbours)?;
+        }
+        writeln!(f, "#init\n({})\n#depth\n{}", self.init, self.depth)?;
+        if !self.turns.is_empty() {
+            writeln!(f, "#turnorder\n{}", self.turns)?;
+        }
+        writeln!(f, "#blackgoal\n{}\n#whitegoal\n{}", self.black_goal, self.white_goal)?;
+        if !self.stuck.is_empty() {
+            writeln!(f, "#nomove\n{}", self.stuck)?;
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn position_encodings() {
+    for encoding in [PositionEncoding::Log, PositionEncoding::OneHot, PositionEncoding::Order, PositionEncoding::CellIndex] {
+        for size in [Size { x: 1, y: 1 }, Size { x: 1, y: 3 }, Size { x: 3, y: 1 }, Size { x: 2, y: 3 }] {
+            let truth = atom();
+            let holds = |formula: Formula| truth.exists(!!truth & formula).evaluate();
+            let position = Position::new(encoding, size, 0, truth);
+            let cells: Vec<(i64, i64)> = (0..size.x).flat_map(|x| (0..size.y).map(move |y| (x, y))).collect();
+            for (i, &(x, y)) in cells.iter().enumerate() {
+                assert!(holds(position.exists(position.valid() & position.at(x, y))), "{encoding:?} misses ({x},{y}) on {size:?}");
+                for &(bx, by) in &cells[i + 1..] {
+                    assert!(!holds(position.exists(position.valid() & position.at(x, y) & position.at(bx, by))), "{encoding:?} on {size:?}");
+                }
+            }
+            let on_board = position.any(cells.iter().map(|&(x, y)| position.at(x, y)));
+            assert!(holds(position.forall(position.valid().implies(on_board))), "{encoding:?} leaves {size:?}");
+        }
+    }
+}
+
+#[test]
+fn last_effect_wins() {
+    let problem = &Game { black_goal: "(black(?x,?y))", ..GAME }.to_string();
+    for (first, last, wins) in [("white", "black", true), ("black", "white", false)] {
+        let domain = format!("
+#blackactions
+:action occupy
+:parameters (?x, ?y)
+:precondition (open(?x,?y))
+:effect ({first}(?x,?y) {last}(?x,?y))
+#whiteactions
+:action occupy
+:parameters (?x, ?y)
+:precondition (open(?x,?y))
+:effect (white(?x,?y))
+");
+        assert_eq!(solver::solve(&parse_problem(problem).unwrap(), &parse_domain(&domain).unwrap()).is_some(), wins);
+        if depqbf_available() {
+            for transition in [solver_qbf::Transition::Chained, solver_qbf::Transition::Frame] {
+                let options = solver_qbf::Options { transition, ..Default::default() };
+                let qbf = solver_qbf::solve(parse_problem(problem).unwrap(), parse_domain(&domain).unwrap(), options).check();
+                assert_eq!(qbf, wins, "{transition:?} with {last} last");
+            }
+        }
+    }
+}
+
+/// White can never move.
+const WHITE_STUCK: &str = "
+#blackactions
+:action occupy
+:parameters (?x, ?y)
+:precondition (open(?x,?y))
+:effect (black(?x,?y))
+#whiteactions
+:action occupy
+:parameters (?x, ?y)
+:precondition (open(?x,?y) black(?x,?y))
+:effect (white(?x,?y))
+";
+
+/// Black can only move once white holds the corner, which white can always take.
+const BLACK_STUCK: &str = "
+#blackactions
+:action occupy
+:parameters (?x, ?y)
+:precondition (open(?x,?y) white(xmin,ymin))
+:effect (black(?x,?y))
+#whiteactions
+:action occupy
+:parameters (?x, ?y)
+:precondition (open(xmin,ymin))
+:effect (white(xmin,ymin))
+";
+
+#[test]
+fn white_stuck() {
+    let game = |depth, stuck| Game { depth, stuck, ..GAME }.to_string();
+    assert!(black_wins(&game(2, "loss"), WHITE_STUCK));
+    assert!(!black_wins(&game(2, "draw"), WHITE_STUCK));
+    assert!(!black_wins(&game(2, "pass"), WHITE_STUCK));
+    assert!(black_wins(&game(3, "loss"), WHITE_STUCK));
+    assert!(!black_wins(&game(3, "draw"), WHITE_STUCK));
+    assert!(black_wins(&game(3, "pass"), WHITE_STUCK));
+}
+
+#[test]
+fn black_stuck() {
+    let game = |stuck| Game { depth: 3, black_goal: "(black(?x,?y))", stuck, ..GAME }.to_string();
+    assert!(!black_wins(&game("loss"), BLACK_STUCK));
+    assert!(!black_wins(&game("draw"), BLACK_STUCK));
+    assert!(black_wins(&game("pass"), BLACK_STUCK));
+}
+
+#[test]
+fn default_is_loss() {
+    let problem = Game { depth: 2, ..GAME }.to_string();
+    assert_eq!(parse_problem(&problem).unwrap().stuck, crate::bddl::Stuck::Loss);
+    assert!(black_wins(&problem, WHITE_STUCK));
+}
+
+#[test]
+fn keywords_as_names() {
+    let domain = WHITE_STUCK.replacen(":action occupy", ":action pass", 1).replacen(":action occupy", ":action AND", 1);
+    assert!(black_wins(&Game { depth: 3, stuck: "pass", ..GAME }.to_string(), &domain));
+    assert!(parse_problem(&Game { depth: 3, stuck: "positions", ..GAME }.to_string()).is_err());
+}
+
+#[test]
+fn goals_after_pass() {
+    let black = Game { init: "black(0,1) black(1,1)", stuck: "pass", ..GAME };
+    assert!(black_wins(&black.to_string(), BLACK_STUCK));
+    let white = Game { init: "white(0,1) white(1,1)", depth: 3, stuck: "pass", ..GAME };
+    assert!(!black_wins(&white.to_string(), WHITE_STUCK));
+}
+
+#[test]
+fn compiled_goals() {
+    let compile = |black_goal: &str| {
+        let problem = parse_problem(&Game { size: "3 3", black_goal, ..GAME }.to_string()).unwrap();
+        goals::compile(&problem.black_goals, &problem)
+    };
+    let row = "(black(?x,?y) black(?x+1,?y) black(?x+2,?y))";
+    let rows = compile(row);
+    assert_eq!(rows.len(), 3);
+    assert_eq!(compile(&format!("{row}\n{row}")), rows);
+    assert_eq!(compile(&format!("{row}\n(black(?x,?y) black(?x+1,?y))")).len(), 6);
+    assert_eq!(compile(&format!("{row}\n(black(?x,?y) black(?x+3,?y))")), rows);
+}
+
+/// Black occupies a cell where `precondition` holds, white any open one.
+fn occupy(precondition: &str, effect: &str) -> String {
+    format!("
+#blackactions
+:action occupy
+:parameters (?x, ?y)
+:precondition ({precondition})
+:effect ({effect})
+#whiteactions
+:action occupy
+:parameters (?x, ?y)
+:precondition (open(?x,?y))
+:effect (white(?x,?y))
+")
+}
+
+/// The board fills up after a goal is reached, leaving the side to move stuck.
+#[test]
+fn play_stops_at_a_goal() {
+    let domain = occupy("open(?x,?y)", "black(?x,?y)");
+    for stuck in ["loss", "draw", "pass"] {
+        let won = Game { size: "2 1", depth: 4, black_goal: "(black(?x,?y))", stuck, ..GAME };
+        assert!(black_wins(&won.to_string(), &domain), "{stuck}");
+        let lost = Game { size: "3 1", depth: 4, white_goal: "(white(?x,?y))", stuck, ..GAME };
+        assert!(!black_wins(&lost.to_string(), &domain), "{stuck}");
+    }
+}
+
+#[test]
+fn offsets_off_the_board() {
+    let problem = Game { black_goal: "(black(?x,?y))", ..GAME }.to_string();
+    assert!(!black_wins(&problem, &occupy("open(?x,?y) open(?x+2,?y)", "black(?x,?y)")));
+    assert!(!black_wins(&problem, &occupy("open(?x,?y) open(?x,?y-2)", "black(?x,?y)")));
+    assert!(!black_wins(&problem, &occupy("open(?x,?y) open(2,?y)", "black(?x,?y)")));
+}
+
+#[test]
+fn ymax_on_a_wide_board() {
+    let problem = Game { size: "3 2", black_goal: "(black(?x,ymax))", ..GAME }.to_string();
+    assert!(black_wins(&problem, &occupy("open(?x,?y)", "black(?x,ymax)")));
+}
+
+#[test]
+fn parse_error_names_construct() {
+    let positions = "#boardsize\n3 3\n#positions\n(a1 a2)\n";
+    let error = parse_problem(positions).unwrap_err();
+    assert_eq!((error.line, error.column), (3, 2));
+    assert_eq!(error.construct(), "#positions");
+
+    let error = parse_domain(&WHITE_STUCK.replace("(?x, ?y)", "(?x ?y)")).unwrap_err();
+    assert_eq!(error.construct(), "#blackactions: ?");
+    assert_eq!(error.expected, ["comma"]);
+}
+
+const RING_DOMAIN: &str = "
+#blackactions
+:action occupy
+:parameters (?p)
+:precondition (open(?p))
+:effect (black(?p))
+#whiteactions
+:action occupy
+:parameters (?p)
+:precondition (open(?p))
+:effect (white(?p))
+";
+
+/// Three positions where only the neighbour relation closes the ring.
+const RING: Game<'static> = Game {
+    positions: "a b c",
+    neighbours: "#neighbours next\n(a b) (b c) (c a)",
+    black_goal: "(black(?p) black(next(?p)))",
+    white_goal: "(white(?p) white(next(?p)))",
+    ..GAME
+};
+
+#[test]
+fn neighbour_relations() {
+    let ring = |depth, init| Game { depth, init, ..RING }.to_string();
+    assert!(!black_wins(&ring(1, ""), RING_DOMAIN));
+    assert!(black_wins(&ring(3, ""), RING_DOMAIN));
+    assert!(black_wins(&ring(1, "black(c)"), RING_DOMAIN));
+    assert!(!black_wins(&ring(3, "white(a)"), RING_DOMAIN));
+    let pairs = RING_DOMAIN.replacen("(open(?p))\n:effect (black(?p))", "(open(?p) open(next(?p)))\n:effect (black(?p) black(next(?p)))", 1);
+    assert!(black_wins(&ring(1, ""), &pairs));
+
+    let problem = parse_problem(&ring(3, "white(a)")).unwrap();
+    assert_eq!(parse_problem(&problem.to_string()).unwrap().to_string(), problem.to_string());
+    assert_eq!(parse_problem(&ring(3, "white(d)")).unwrap_err().construct(), "#init: d");
+    assert_eq!(parse_problem(&ring(3, "").replace("(c a)", "(a c)")).unwrap_err().construct(), "#neighbours: a");
+
+    let domain = parse_domain(RING_DOMAIN).unwrap();
+    assert_eq!(domain.check(&problem), Ok(()));
+    let undeclared = Err("neighbour relation next is not declared by the problem".to_owned());
+    let unrelated = ring(3, "").replace("#neighbours next", "#neighbours prev");
+    assert_eq!(domain.check(&parse_problem(&unrelated).unwrap()), undeclared);
+    let unrelated = unrelated.replace("next(?p)", "prev(?p)");
+    assert_eq!(parse_domain(&pairs).unwrap().check(&parse_problem(&unrelated).unwrap()), undeclared);
+}
+
+/// Pieces step towards the opponent's side and capture diagonally, black up and white down.
+const BREAKTHROUGH: &str = "
+#blackactions
+:action forward
+:parameters (?x, ?y)
+:precondition (black(?x,?y) open(?x,?y-1))
+:effect (open(?x,?y) black(?x,?y-1))
+:action capture
+:parameters (?x, ?y)
+:precondition (black(?x,?y) white(?x+1,?y-1))
+:effect (open(?x,?y) black(?x+1,?y-1))
+#whiteactions
+:action forward
+:parameters (?x, ?y)
+:precondition (white(?x,?y) open(?x,?y+1))
+:effect (open(?x,?y) white(?x,?y+1))
+:action capture
+:parameters (?x, ?y)
+:precondition (white(?x,?y) black(?x-1,?y+1))
+:effect (open(?x,?y) white(?x-1,?y+1))
+";
+
+#[test]
+fn moves_and_captures() {
+    let game = |depth, init, stuck| Game { size: "2 3", init, depth, black_goal: "(black(?x,ymin))", white_goal: "(white(?x,ymax))", stuck, ..GAME }.to_string();
+    // Black's only move walks into white's capture, after which black has no piece left.
+    assert!(!black_wins(&game(3, "black(0,2) white(1,0)", ""), BREAKTHROUGH));
+    // Black captures, leaving white without a move.
+    assert!(black_wins(&game(2, "black(0,2) white(1,1)", ""), BREAKTHROUGH));
+    assert!(!black_wins(&game(2, "black(0,2) white(1,1)", "draw"), BREAKTHROUGH));
+    assert!(black_wins(&game(3, "black(0,2) white(1,1)", "pass"), BREAKTHROUGH));
+}
+
+/// Black acts on its only cell with `effect`, white has no actions.
+fn act(effect: &str) -> String {
+    format!("
+#blackactions
+:action act
+:parameters (?x, ?y)
+:precondition ()
+:effect ({effect})
+#whiteactions
+")
+}
+
+/// A single cell, with no goal for white.
+const ONE_CELL: Game<'static> = Game { size: "1 1", white_goal: "", ..GAME };
+
+#[test]
+fn effect_order_and_deletions() {
+    let wins = |effect: &str, init, goal: &str| black_wins(&Game { init, black_goal: &format!("({goal})"), ..ONE_CELL }.to_string(), &act(effect));
+    assert!(wins("white(?x,?y) black(?x,?y)", "", "black(?x,?y)"));
+    assert!(!wins("black(?x,?y) white(?x,?y)", "", "black(?x,?y)"));
+    assert!(wins("NOT(white(?x,?y))", "white(0,0)", "open(?x,?y)"));
+    assert!(!wins("NOT(black(?x,?y))", "white(0,0)", "open(?x,?y)"));
+    // Deletions read the board before the move, and only count when they open the cell.
+    assert!(wins("black(?x,?y) NOT(black(?x,?y))", "", "black(?x,?y)"));
+    assert!(wins("black(?x,?y) NOT(white(?x,?y))", "white(0,0)", "open(?x,?y)"));
+    assert_eq!(parse_domain(&act("NOT(open(?x,?y))")).unwrap_err().construct(), "#blackactions: open");
+}
+
+/// Black moves its piece to any open cell, a from/to move with a second coordinate pair.
+const JUMP: &str = "
+#blackactions
+:action jump
+:parameters (?x, ?y, ?tx, ?ty)
+:precondition (black(?x,?y) open(?tx,?ty))
+:effect (open(?x,?y) black(?tx,?ty))
+#whiteactions
+:action occupy
+:parameters (?x, ?y)
+:precondition (open(?x,?y))
+:effect (white(?x,?y))
+";
+
+/// A row of three cells where black wins on the last one.
+const JUMP_ROW: Game<'static> = Game { size: "3 1", black_goal: "(black(xmax,?y))", ..GAME };
+
+#[test]
+fn extra_parameters() {
+    let jump = |depth, init| Game { depth, init, ..JUMP_ROW }.to_string();
+    assert!(black_wins(&jump(1, "black(0,0) white(1,0)"), JUMP));
+    assert!(!black_wins(&jump(1, "black(0,0) white(2,0)"), JUMP));
+    // Only the cell after the target counts, which has to stay on the board.
+    let beyond = JUMP.replace("open(?tx,?ty))", "open(?tx+1,?ty))").replace("black(?tx,?ty))", "black(?tx+1,?ty))");
+    assert!(black_wins(&jump(1, "black(0,0) white(1,0)"), &beyond));
+    assert!(!black_wins(&jump(3, "white(0,0)"), &beyond));
+
+    // On a ring, black copies onto an open cell whose successor is black.
+    let copy = RING_DOMAIN.replacen("(?p)\n:precondition (open(?p))", "(?p, ?q)\n:precondition (open(?p) open(?q) black(next(?q)))", 1);
+    assert!(black_wins(&Game { init: "black(a)", ..RING }.to_string(), &copy));
+    assert!(!black_wins(&Game { init: "black(a) white(c)", ..RING }.to_string(), &copy));
+
+    let domain = parse_domain(JUMP).unwrap();
+    assert_eq!(domain.black_actions[0].parameters, ["tx", "ty"]);
+    assert_eq!(parse_domain(&domain.to_string()).unwrap().to_string(), domain.to_string());
+    let error = parse_domain(&JUMP.replace("?tx, ?ty)", "?tx)")).unwrap_err();
+    assert_eq!(error.construct(), "#blackactions: jump");
+    assert_eq!(error.reason.as_deref(), Some("unknown parameter ty"));
+    assert!(error.to_string().ends_with(": unknown parameter ty in #blackactions"), "{error}");
+}
+
+#[test]
+fn nested_conditions() {
+    let wins = |precondition: &str, init, goal: &str| {
+        let domain = act("black(?x,?y)").replace(":precondition ()", &format!(":precondition ({precondition})"));
+        black_wins(&Game { init, black_goal: &format!("({goal})"), ..ONE_CELL }.to_string(), &domain)
+    };
+    assert!(wins("", "", "OR(white(?x,?y) black(?x,?y))"));
+    assert!(!wins("", "", "OR()"));
+    assert!(wins("NOT(OR(white(?x,?y) black(?x,?y)))", "", "black(?x,?y)"));
+    assert!(!wins("NOT(OR(white(?x,?y) black(?x,?y)))", "white(0,0)", "black(?x,?y)"));
+    assert!(wins("OR(AND(open(?x,?y)) white(?x,?y))", "white(0,0)", "black(?x,?y)"));
+    // A cell off the board is false, so only its negation as a condition holds.
+    assert!(wins("", "", "black(?x,?y) NOT(AND(black(?x+1,?y)))"));
+    assert!(!wins("", "", "black(?x,?y) NOT(black(?x+1,?y))"));
+    assert!(wins("", "", "black(?x,?y) NOT(NOT(black(?x+1,?y)))"));
+    assert!(wins("NOT(NOT(open(?x,?y)))", "", "black(?x,?y)"));
+    assert!(!wins("NOT(NOT(open(?x,?y)))", "white(0,0)", "black(?x,?y)"));
+
+    let problem = Game { black_goal: "(OR(black(?x,?y) NOT(AND(open(?x,?y) white(?x,?y-1)))))", ..ONE_CELL };
+    let domain = act("black(?x,?y)").replace(":precondition ()", ":precondition (NOT(OR(black(?x,?y))) NOT(NOT(white(?x+1,?y))))");
+    let problem = parse_problem(&problem.to_string()).unwrap();
+    assert_eq!(parse_problem(&problem.to_string()).unwrap().to_string(), problem.to_string());
+    let domain = parse_domain(&domain).unwrap();
+    assert_eq!(parse_domain(&domain.to_string()).unwrap().to_string(), domain.to_string());
+}
+
+#[test]
+fn declared_predicates() {
+    let declared = |effect: &str| format!("#predicates\n(wall marked king)\n{}", act(effect));
+    let wins = |effect: &str, init, goal: &str| black_wins(&Game { init, black_goal: &format!("({goal})"), ..ONE_CELL }.to_string(), &declared(effect));
+    assert!(wins("marked(?x,?y)", "", "marked(?x,?y)"));
+    assert!(!wins("king(?x,?y)", "", "marked(?x,?y)"));
+    assert!(wins("NOT(wall(?x,?y)) black(?x,?y) king(?x,?y)", "", "NOT(open(?x,?y)) NOT(black(?x,?y))"));
+    assert!(wins("NOT(wall(?x,?y))", "wall(0,0)", "open(?x,?y)"));
+    assert!(!wins("NOT(king(?x,?y))", "wall(0,0)", "open(?x,?y)"));
+
+    let domain = parse_domain(&declared("king(?x,?y)")).unwrap();
+    assert_eq!(parse_domain(&domain.to_string()).unwrap().to_string(), domain.to_string());
+    let problem = parse_problem(&Game { init: "wall(0,0)", black_goal: "(king(?x,?y))", ..ONE_CELL }.to_string()).unwrap();
+    assert_eq!(domain.check(&problem), Ok(()));
+    assert_eq!(parse_domain(&act("black(?x,?y)")).unwrap().check(&problem), Err("predicate wall is not declared by the domain".to_owned()));
+    assert_eq!(parse_domain(&act("king(?x,?y)")).unwrap_err().construct(), "#blackactions: act");
+    assert_eq!(parse_domain(&format!("#predicates\n(wall wall)\n{}", act(""))).unwrap_err().construct(), "#predicates: wall");
+}
+
+/// Black drops pieces while it has some in hand, white only counts its turns.
+const COUNTER: &str = "
+#variables
+(hand 3) (turns 7)
+#blackactions
+:action drop
+:parameters (?x, ?y)
+:precondition (open(?x,?y) NOT(hand = 0))
+:effect (black(?x,?y) hand - 1)
+#whiteactions
+:action wait
+:parameters (?x, ?y)
+:precondition ()
+:effect (turns + 1)
+";
+
+/// Five moves on a row of three cells, with no goal for white.
+const COUNTER_ROW: Game<'static> = Game { size: "3 1", depth: 5, white_goal: "", ..GAME };
+
+#[test]
+fn variables() {
+    let counter = |init, goal: &str| Game { init, black_goal: &format!("({goal})"), ..COUNTER_ROW }.to_string();
+    let row = "black(?x,?y) black(?x+1,?y) black(?x+2,?y)";
+    assert!(black_wins(&counter("hand = 3", row), COUNTER));
+    assert!(!black_wins(&counter("hand = 2", row), COUNTER));
+    assert!(!black_wins(&counter("", row), COUNTER));
+    assert!(black_wins(&counter("hand = 3", "turns = 2"), COUNTER));
+    assert!(!black_wins(&counter("hand = 2", "turns = 2"), COUNTER));
+    // Counting past the maximum leaves a variable unchanged.
+    let flag = COUNTER.replace("(turns 7)", "(turns)");
+    assert!(black_wins(&counter("hand = 3", &format!("turns = 1 {row}")), &flag));
+
+    let domain = parse_domain(COUNTER).unwrap();
+    assert_eq!(parse_domain(&domain.to_string()).unwrap().to_string(), domain.to_string());
+    let problem = parse_problem(&counter("black(0,0) hand = 2", "NOT(turns = 2) OR(hand = 1)")).unwrap();
+    assert_eq!(parse_problem(&problem.to_string()).unwrap().to_string(), problem.to_string());
+    assert_eq!(domain.check(&problem), Ok(()));
+    assert_eq!(domain.check(&parse_problem(&counter("hand = 4", "")).unwrap()), Err("variable hand cannot start at 4".to_owned()));
+    assert_eq!(domain.check(&parse_problem(&counter("", "coins = 1")).unwrap()), Err("variable coins is not declared by the domain".to_owned()));
+    assert_eq!(parse_domain(&COUNTER.replace("hand - 1", "hand = 4")).unwrap_err().construct(), "#blackactions: drop");
+    assert_eq!(parse_domain(&COUNTER.replace("(hand 3) ", "")).unwrap_err().construct(), "#blackactions: drop");
+}
+
+/// Both players take any open cell and win with two adjacent ones.
+const PLACE: &str = "
+#blackactions
+:action place
+:parameters (?x, ?y)
+:precondition (open(?x,?y))
+:effect (black(?x,?y))
+#whiteactions
+:action place
+:parameters (?x, ?y)
+:precondition (open(?x,?y))
+:effect (white(?x,?y))
+";
+
+/// Three moves on a row of three cells.
+const PLACE_ROW: Game<'static> = Game { size: "3 1", depth: 3, ..GAME };
+
+#[test]
+fn turn_order() {
+    assert!(black_wins(&PLACE_ROW.to_string(), PLACE));
+    assert!(!black_wins(&Game { turns: "white", ..PLACE_ROW }.to_string(), PLACE));
+    assert!(!black_wins(&Game { depth: 2, ..PLACE_ROW }.to_string(), PLACE));
+    assert!(black_wins(&Game { depth: 2, turns: "black 2", ..PLACE_ROW }.to_string(), PLACE));
+    // Connect6 style, white places one stone and then both players two.
+    assert!(!black_wins(&Game { turns: "white 1 2", ..PLACE_ROW }.to_string(), PLACE));
+    assert!(black_wins(&Game { size: "4 1", turns: "white 1 2", ..PLACE_ROW }.to_string(), PLACE));
+
+    let problem = parse_problem(&Game { size: "4 1", turns: "white 1 2", ..PLACE_ROW }.to_string()).unwrap();
+    let movers: Vec<_> = (0..6).map(|ply| problem.turns.mover(ply)).collect();
+    assert_eq!(movers, [Player::White, Player::Black, Player::Black, Player::White, Player::White, Player::Black]);
+    assert_eq!(solver::solve(&problem, &parse_domain(PLACE).unwrap()), Some(None));
+    assert_eq!(parse_problem(&problem.to_string()).unwrap().to_string(), problem.to_string());
+    let error = parse_problem(&Game { size: "4 1", turns: "black 1 0", ..PLACE_ROW }.to_string()).unwrap_err();
+    assert_eq!(error.construct(), "#turnorder: 0");
+    assert_eq!(error.reason.as_deref(), Some("a turn has at least one move"));
+}
+
+/// The outcome, asserting that all available backends agree on it.
+fn outcome(problem: &str, domain: &str) -> Outcome {
+    let (problem, domain) = (parse_problem(problem).unwrap(), parse_domain(domain).unwrap());
+    outcome::classify(&problem, &domain, |problem, domain| Some(black_wins(&problem.to_string(), &domain.to_string()))).unwrap()
+}
+
+#[test]
+fn outcomes() {
+    assert_eq!(outcome(&PLACE_ROW.to_string(), PLACE), Outcome::BlackWins);
+    assert_eq!(outcome(&Game { turns: "white", ..PLACE_ROW }.to_string(), PLACE), Outcome::WhiteWins);
+    assert_eq!(outcome(&Game { depth: 2, ..PLACE_ROW }.to_string(), PLACE), Outcome::Draw);
+    assert_eq!(outcome(&Game { depth: 2, stuck: "draw", ..GAME }.to_string(), WHITE_STUCK), Outcome::Draw);
+    assert_eq!(outcome(&Game { depth: 3, black_goal: "(black(?x,?y))", ..GAME }.to_string(), BLACK_STUCK), Outcome::WhiteWins);
+
+    let domain = parse_domain(PLACE).unwrap();
+    let answers = |problem: &str| {
+        let problem = parse_problem(problem).unwrap();
+        [Query::BlackWins, Query::WhiteWins, Query::NoLoss, Query::Draw]
+            .map(|query| query.answer(&problem, &domain, |problem, domain| Some(solver::solve(problem, domain).is_some())).unwrap())
+    };
+    assert_eq!(answers(&PLACE_ROW.to_string()), [true, false, true, false]);
+    assert_eq!(answers(&Game { turns: "white", ..PLACE_ROW }.to_string()), [false, true, false, false]);
+    assert_eq!(answers(&Game { depth: 2, ..PLACE_ROW }.to_string()), [false, false, true, true]);
+}
+
+#[test]
+fn play_against_solver() {
+    let problem = parse_problem(&PLACE_ROW.to_string()).unwrap();
+    let domain = parse_domain(PLACE).unwrap();
+    let explicit = |problem: &Problem, domain: &Domain| Some(solver::solve(problem, domain).is_some());
+    let mut output = Vec::new();
+    let winner = play::play(&problem, &domain, Player::White, "place 5 0\nplace 0 0\n".as_bytes(), &mut output, explicit).unwrap();
+    let output = String::from_utf8(output).unwrap();
+    assert_eq!(winner, Some(Player::Black), "{output}");
+    assert!(output.contains("black plays place 1 0\n  0 1 2\n0 . X .\n"), "{output}");
+    assert!(output.contains("not an applicable move, try one of: place 0 0, place 2 0"), "{output}");
+    assert!(output.ends_with("0 O X X\n\nblack wins\n"), "{output}");
+
+    let winner = play::play(&problem, &domain, Player::Black, "place 0 0\nplace 2 0\n".as_bytes(), Vec::new(), explicit).unwrap();
+    assert_eq!(winner, None);
+    assert!(play::play(&problem, &domain, Player::Black, "".as_bytes(), Vec::new(), explicit).is_err());
+
+    let turns = parse_problem(&Game { size: "4 1", turns: "white 1 2", ..PLACE_ROW }.to_string()).unwrap().turns;
+    assert_eq!(turns.after(2), Turns { first: Player::Black, schedule: vec![1, 2] });
+    assert_eq!(turns.after(3), Turns { first: Player::White, schedule: vec![2] });
+    assert_eq!((0..6).map(|ply| turns.after(1).mover(ply)).collect::<Vec<_>>(), (1..7).map(|ply| turns.mover(ply)).collect::<Vec<_>>());
+}
+
+#[test]
+fn rendering() {
+    let problem = parse_problem(&Game { init: "black(1,0) hand = 2", black_goal: "(turns = 5)", ..COUNTER_ROW }.to_string()).unwrap();
+    let domain = parse_domain(COUNTER).unwrap();
+    let moves = record::parse_moves(&problem, "drop 0 0, wait 2 0,drop 2 0").unwrap();
+    let positions = render::line(&problem, &domain, &moves).unwrap();
+    assert_eq!(render::pages(&positions[..2]), "initial position\n  0 1 2\n0 . X .\nhand = 2\nturns = 0\n\n1. black drop 0 0\n  0 1 2\n0 X X .\nhand = 1\nturns = 0\n");
+    assert_eq!(positions[3].1.to_string(), "  0 1 2\n0 X X X\nhand = 0\nturns = 1\n");
+
+    let svg = render::svg(&problem, &domain, &positions[3].1);
+    assert_eq!(svg.matches("<circle").count(), 3);
+    assert!(svg.contains(">turns = 1</text>"));
+    let animation = render::animation(&problem, &domain, &positions, 2);
+    assert_eq!(animation.matches("<g ").count(), 4);
+    assert!(animation.contains("begin=\"6s\" dur=\"indefinite\""));
+
+    assert_eq!(render::line(&problem, &domain, &record::parse_moves(&problem, "drop 0 0, wait 0 0, drop 0 0").unwrap()).unwrap_err(), "move 3: drop 0 0 is not an applicable move");
+    assert!(record::parse_moves(&problem, "drop 0").is_err());
+    let problem = parse_problem(&PLACE_ROW.to_string()).unwrap();
+    let domain = parse_domain(PLACE).unwrap();
+    let positions = render::line(&problem, &domain, &record::parse_moves(&problem, "place 0 0, place 2 0, place 1 0").unwrap()).unwrap();
+    assert_eq!(positions[3].0, "3. black place 1 0, black wins with (black(?x,?y) black(?x+1,?y))");
+    let late = record::parse_moves(&problem, "place 0 0, place 2 0, place 1 0, place 0 0").unwrap();
+    assert_eq!(render::line(&problem, &domain, &late).unwrap_err(), "move 4: the game is over");
+
+    // The extra parameters pick the target, the move is not played with the first one found.
+    let problem = parse_problem(&Game { init: "black(0,0)", depth: 3, ..JUMP_ROW }.to_string()).unwrap();
+    let domain = parse_domain(JUMP).unwrap();
+    let positions = render::line(&problem, &domain, &record::parse_moves(&problem, "jump 0 0 2 0").unwrap()).unwrap();
+    assert_eq!(positions[1].0, "1. black jump 0 0 2 0, black wins with (black(xmax,?y))");
+    assert_eq!(positions[1].1.pred((2, 0)), &Pred::Black);
+    let taken = record::parse_moves(&problem, "jump 0 0 1 0, occupy 2 0, jump 1 0 2 0").unwrap();
+    assert_eq!(render::line(&problem, &domain, &taken).unwrap_err(), "move 3: jump 1 0 2 0 is not an applicable move");
+}
+
+#[test]
+fn replay_records() {
+    let src = "problem place.ig # three cells\ndomain place.ig\nblack place 0 0\n\nwhite place 2 0\nblack place 1 0\n";
+    let record: Record = src.parse().unwrap();
+    assert_eq!(record.moves[1], record::Move { player: Player::White, action: "place".to_owned(), x: 2, y: 0, args: Vec::new() });
+    assert_eq!(record.to_string().parse::<Record>().unwrap(), record);
+    assert_eq!("problem a\ndomain b\nblack place 0\n".parse::<Record>().unwrap_err(), "line 3: cannot read black place 0");
+    assert!("problem a\nblack pass\n".parse::<Record>().is_err());
+
+    let problem = parse_problem(&PLACE_ROW.to_string()).unwrap();
+    let domain = parse_domain(PLACE).unwrap();
+    let (lines, _) = record::replay(&problem, &domain, &record.moves).unwrap();
+    assert_eq!(lines, ["1. black place 0 0", "2. white place 2 0", "3. black place 1 0, black wins with (black(?x,?y) black(?x+1,?y))"]);
+    let captions: Vec<String> = render::line(&problem, &domain, &record.moves).unwrap().into_iter().skip(1).map(|(caption, _)| caption).collect();
+    assert_eq!(captions, lines);
+    let (_, board) = record::replay(&problem, &domain, &record.moves[..1]).unwrap();
+    let after = board.problem(1);
+    assert_eq!((after.depth, after.turns.first, after.init.len()), (2, Player::White, 1));
+    assert!(!black_wins(&after.to_string(), PLACE));
+
+    let wrong = |src: &str| record::replay(&problem, &domain, &src.parse::<Record>().unwrap().moves).unwrap_err();
+    assert_eq!(wrong("problem a\ndomain b\nwhite place 0 0\n"), "move 1: black is to move");
+    assert_eq!(wrong("problem a\ndomain b\nblack place 0 0\nwhite place 0 0\n"), "move 2: place 0 0 is not an applicable move");
+    assert_eq!(wrong("problem a\ndomain b\nblack jump 0 0\n"), "move 1: jump 0 0 is not an applicable move");
+    assert_eq!(wrong("problem a\ndomain b\nblack pass\n"), "move 1: pass is not an applicable move");
+    assert_eq!(wrong(&format!("{src}white place 0 0\n")), "move 4: the game is over");
+}
+
+#[test]
+fn positions_from_moves() {
+    let problem = parse_problem(&Game { size: "4 1", depth: 5, turns: "white 1 2", ..PLACE_ROW }.to_string()).unwrap();
+    let domain = parse_domain(PLACE).unwrap();
+    let moves = record::parse_moves(&problem, "place 1 0, place 3 0,place 1 0").unwrap();
+    assert_eq!(moves.iter().map(|m| m.player).collect::<Vec<_>>(), [Player::White, Player::Black, Player::Black]);
+    let after = record::position(&problem, &domain, &moves[..2]).unwrap();
+    assert_eq!((after.depth, after.turns.clone()), (3, Turns { first: Player::Black, schedule: vec![1, 2] }));
+    assert!(black_wins(&after.to_string(), PLACE));
+    assert_eq!(record::position(&problem, &domain, &moves).unwrap_err(), "move 3: place 1 0 is not an applicable move");
+    assert!(record::parse_moves(&problem, "place 1").is_err());
+
+    let won = record::parse_moves(&problem, "place 0 0, place 2 0, place 3 0").unwrap();
+    assert_eq!(record::position(&problem, &domain, &won).unwrap_err(), "move 3: the game is over");
+
+    let board = parser::PositionParser::new().parse(lexer::tokens("(white(1,0) black(3,0))")).unwrap();
+    let (init, values) = resolve_init(&problem.positions, board).unwrap();
+    let explicit = problem.with_board(init.clone(), values.clone(), 2).unwrap();
+    assert_eq!(explicit.to_string(), after.to_string());
+    assert_eq!(problem.with_board(init, values, 6).unwrap_err(), "move 6 exceeds the depth 5");
+}
+
+#[test]
+fn z3_first_move() {
+    let domain = parse_domain(PLACE).unwrap();
+    let solve = |problem: &str| solver_z3::solve_move(&parse_problem(problem).unwrap(), &domain, None).unwrap();
+    // Only the middle cell wins.
+    assert_eq!(solve(&PLACE_ROW.to_string()), Some(Some(("place".to_owned(), 1, 0, Vec::new()))));
+    assert_eq!(solve(&PLACE_ROW.to_string()), solver::solve(&parse_problem(&PLACE_ROW.to_string()).unwrap(), &domain));
+    assert_eq!(solve(&Game { size: "4 1", turns: "white 1 2", ..PLACE_ROW }.to_string()), Some(None));
+    assert_eq!(solve(&Game { depth: 2, ..PLACE_ROW }.to_string()), None);
+    let stuck = parse_problem(&Game { depth: 3, black_goal: "(black(?x,?y))", stuck: "pass", ..GAME }.to_string()).unwrap();
+    assert_eq!(solver_z3::solve_move(&stuck, &parse_domain(BLACK_STUCK).unwrap(), None).unwrap(), Some(Some((solver::PASS.to_owned(), 0, 0, Vec::new()))));
+}
+
+#[test]
+fn smt2_export() {
+    let domain = parse_domain(PLACE).unwrap();
+    for (problem, wins) in [(PLACE_ROW.to_string(), true), (Game { depth: 2, ..PLACE_ROW }.to_string(), false), (Game { size: "4 1", turns: "white 1 2", ..PLACE_ROW }.to_string(), true)] {
+        let problem = parse_problem(&problem).unwrap();
+        let context = Context::new(&Default::default());
+        let script = solver_z3::smt2(&solver_z3::solve(&context, &problem, &domain));
+        assert!(script.starts_with("(declare-datatypes") && script.ends_with("(check-sat)\n"), "{script}");
+        let solver = Solver::new(&context);
+        solver.from_string(script.as_str());
+        assert_eq!(solver.check() == SatResult::Sat, wins, "{script}");
+    }
+}
+
+#[test]
+fn z3_first_move_arguments() {
+    // Only the jump onto the last cell wins.
+    let problem = parse_problem(&Game { init: "black(0,0) white(1,0)", ..JUMP_ROW }.to_string()).unwrap();
+    let domain = parse_domain(JUMP).unwrap();
+    let first = Some(Some(("jump".to_owned(), 0, 0, vec![2, 0])));
+    assert_eq!(solver_z3::solve_move(&problem, &domain, None).unwrap(), first);
+    assert_eq!(solver::solve(&problem, &domain), first);
+}
+
+#[test]
+fn benchmark_manifest() {
+    for entry in bench::read_manifest(&std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("bench")) {
+        let problem = parse_problem(&std::fs::read_to_string(&entry.problem).unwrap()).unwrap();
+        let domain = parse_domain(&std::fs::read_to_string(&entry.domain).unwrap()).unwrap();
+        // Deeper entries take the explicit solver too long for a test.
+        if problem.depth <= 5 {
+            assert_eq!(Some(solver::solve(&problem, &domain).is_some()), entry.expected, "{}", entry.name);
+        }
+    }
+}
+
+#[test]
+fn benchmark_discovery() {
+    let root = std::env::temp_dir().join(format!("bddl-discover-{}", std::process::id()));
+    for file in ["domain.ig", "tttdomain.ig", "tttproblem.ig", "hex.ig", "sub/tttdomain.ig", "sub/ttt3.ig", "sub/deep/tttxproblem.ig", "sub/deep/hexproblem.ig"] {
+        let path = root.join(file);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, "").unwrap();
+    }
+    let pairs: Vec<(String, String)> = bench::discover(&root).into_iter()
+        .map(|entry| (entry.name, entry.domain.strip_prefix(&root).unwrap().to_string_lossy().into_owned()))
+        .collect();
+    std::fs::remove_dir_all(&root).unwrap();
+    // The longest prefix wins, and among equally long ones the domain of the nearest directory.
+    assert_eq!(pairs, [
+        ("hex".to_owned(), "domain.ig".to_owned()),
+        ("tttproblem".to_owned(), "tttdomain.ig".to_owned()),
+        ("sub/ttt3".to_owned(), "sub/tttdomain.ig".to_owned()),
+        ("sub/deep/hexproblem".to_owned(), "domain.ig".to_owned()),
+        ("sub/deep/tttxproblem".to_owned(), "sub/tttdomain.ig".to_owned()),
+    ]);
+}